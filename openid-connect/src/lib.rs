@@ -7,22 +7,37 @@ extern crate serde_json;
 extern crate serde_urlencoded;
 extern crate rand;
 extern crate base64;
+extern crate sha2;
 extern crate itertools;
 
 pub mod request;
 pub mod response;
+pub mod token;
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use request::{Scope, ResponseType};
+    use hyper::Url;
+
+    use request::{AuthRequest, Scope};
+    use response::AuthResponse;
 
     #[test]
-    fn test() {
-        /*
-        https://accounts.google.com/o/oauth2/v2/auth
-        */
-        let request::AuthRequest::new(Scope::Profile,
-                                      "748001161761-8h45hco16bd6sjgbla3m1qk5pdutu0cu.apps.googleusercontent.com");
+    fn test_round_trip() {
+        let request = AuthRequest::with_state(vec![Scope::Profile],
+                                              "748001161761-8h45hco16bd6sjgbla3m1qk5pdutu0cu.apps.googleusercontent.com",
+                                              "https://127.0.0.1/callback", "abc").unwrap();
+        let url = request.to_url("https://accounts.google.com/o/oauth2/v2/auth").unwrap();
+        assert_eq!(Some("accounts.google.com"), url.host_str());
+
+        // The Authorization Server sends the state back as it was given.
+        let state = url.query_pairs()
+            .find(|&(ref key, _)| key == "state")
+            .map(|(_, value)| value.into_owned())
+            .unwrap();
+        let redirect = Url::parse_with_params("https://127.0.0.1/callback",
+                                              &[("code", "4/P7q7W91"), ("state", &state)]).unwrap();
+        let response = AuthResponse::from_url(&redirect, &request).unwrap();
+        assert_eq!(Some("4/P7q7W91"), response.code());
+        assert_eq!("abc", response.state());
     }
-}
\ No newline at end of file
+}
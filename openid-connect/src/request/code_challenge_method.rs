@@ -0,0 +1,63 @@
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::error::*;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, Serialize)]
+/// Specifies how the code challenge is derived from the code verifier, as defined by
+/// [PKCE](https://tools.ietf.org/html/rfc7636#section-4.2).
+pub enum CodeChallengeMethod {
+    /// The code challenge is the code verifier itself.
+    Plain,
+    /// The code challenge is the unpadded base64url encoding of the SHA-256 hash of the code
+    /// verifier.
+    S256
+}
+
+impl Display for CodeChallengeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl AsRef<str> for CodeChallengeMethod {
+    fn as_ref(&self) -> &str {
+        match *self {
+            CodeChallengeMethod::Plain => "plain",
+            CodeChallengeMethod::S256 => "S256"
+        }
+    }
+}
+
+impl FromStr for CodeChallengeMethod {
+    type Err = Error;
+
+    /// This is case sensitive and the only accepted values are "plain" and "S256", returning an
+    /// [Error](error/struct.error.html) wrapping a [ParseCodeChallengeMethodError](error/enum.ErrorKind.html).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::str::FromStr;
+    /// # use ::openid_connect::request::CodeChallengeMethod;
+    /// assert!(CodeChallengeMethod::from_str("s256").is_err());
+    ///
+    /// assert_eq!(CodeChallengeMethod::from_str("plain").unwrap(), CodeChallengeMethod::Plain);
+    /// assert_eq!(CodeChallengeMethod::from_str("S256").unwrap(), CodeChallengeMethod::S256);
+    /// ```
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(CodeChallengeMethod::Plain),
+            "S256" => Ok(CodeChallengeMethod::S256),
+            _ => Err(Error::from(ErrorKind::ParseCodeChallengeMethodError))
+        }
+    }
+}
+
+impl Default for CodeChallengeMethod {
+    /// The spec requires S256 whenever the client is able to use it.
+    fn default() -> Self {
+        CodeChallengeMethod::S256
+    }
+}
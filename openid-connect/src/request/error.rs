@@ -26,5 +26,15 @@ error_chain! {
         ParseResponseTypeError {
             description("Failed to parse Response Type value!")
         }
+
+        /// Failed to parse a code challenge method as defined by [PKCE](https://tools.ietf.org/html/rfc7636#section-4.3).
+        ParseCodeChallengeMethodError {
+            description("Failed to parse Code Challenge Method value!")
+        }
+
+        /// A code verifier is not between 43 and 128 unreserved characters, as [PKCE](https://tools.ietf.org/html/rfc7636#section-4.1) requires.
+        InvalidCodeVerifierError {
+            description("Code verifier is not valid!")
+        }
     }
 }
\ No newline at end of file
@@ -6,6 +6,7 @@ use hyper::Url;
 use hyper::client::IntoUrl;
 use rand::{self, Rng};
 use base64;
+use sha2::{Digest, Sha256};
 use serde_urlencoded;
 use itertools::Itertools;
 
@@ -13,12 +14,14 @@ mod scope;
 mod display;
 mod prompt;
 mod response_type;
+mod code_challenge_method;
 pub mod error;
 
 pub use self::scope::Scope;
 pub use self::display::Display;
 pub use self::prompt::Prompt;
 pub use self::response_type::ResponseType;
+pub use self::code_challenge_method::CodeChallengeMethod;
 use self::error::*;
 
 #[derive(Debug, Serialize, Clone, PartialEq)]
@@ -35,7 +38,9 @@ pub struct AuthRequest {
     ui_locales: Option<Vec<String>>,
     id_token_hint: Option<String>,
     login_hint: Option<String>,
-    acr_values: Option<Vec<String>>
+    acr_values: Option<Vec<String>>,
+    code_verifier: Option<String>,
+    code_challenge_method: Option<CodeChallengeMethod>
 }
 
 impl AuthRequest {
@@ -71,7 +76,9 @@ impl AuthRequest {
             ui_locales: None,
             id_token_hint: None,
             login_hint: None,
-            acr_values: None
+            acr_values: None,
+            code_verifier: None,
+            code_challenge_method: None
         })
     }
 
@@ -189,6 +196,28 @@ impl AuthRequest {
         self
     }
 
+    /// Protects the authorization code with [PKCE](https://tools.ietf.org/html/rfc7636), using a
+    /// fresh random code verifier which is kept to be sent with the code exchange.
+    pub fn set_pkce(&mut self, method: CodeChallengeMethod) -> Result<&mut Self> {
+        let verifier = gen_verifier()?;
+        self.set_code_verifier(method, verifier)
+    }
+
+    /// Protects the authorization code with [PKCE](https://tools.ietf.org/html/rfc7636), using the
+    /// given code verifier.
+    pub fn set_code_verifier<T>(&mut self, method: CodeChallengeMethod, verifier: T) -> Result<&mut Self> where
+        T: Into<String> {
+
+        let verifier = verifier.into();
+        let unreserved = |c: char| c.is_ascii_alphanumeric() || "-._~".contains(c);
+        if verifier.len() < 43 || verifier.len() > 128 || !verifier.chars().all(unreserved) {
+            bail!(ErrorKind::InvalidCodeVerifierError)
+        }
+        self.code_verifier = Some(verifier);
+        self.code_challenge_method = Some(method);
+        Ok(self)
+    }
+
     pub fn scopes(&self) -> &HashSet<Scope> {
         &self.scope
     }
@@ -233,6 +262,27 @@ impl AuthRequest {
         self.acr_values.as_ref().map(Vec::as_ref)
     }
 
+    /// The secret which proves the code exchange comes from whoever made this request.
+    pub fn code_verifier(&self) -> Option<&str> {
+        self.code_verifier.as_ref().map(String::as_ref)
+    }
+
+    pub fn code_challenge_method(&self) -> Option<&CodeChallengeMethod> {
+        self.code_challenge_method.as_ref()
+    }
+
+    /// The code challenge sent in place of the code verifier, derived as defined by
+    /// [PKCE](https://tools.ietf.org/html/rfc7636#section-4.2).
+    pub fn code_challenge(&self) -> Option<String> {
+        match (self.code_verifier.as_ref(), self.code_challenge_method) {
+            (Some(verifier), Some(CodeChallengeMethod::Plain)) => Some(verifier.clone()),
+            (Some(verifier), Some(CodeChallengeMethod::S256)) => {
+                Some(base64::encode_config(Sha256::digest(verifier.as_bytes()).as_slice(), base64::URL_SAFE_NO_PAD))
+            },
+            _ => None
+        }
+    }
+
     pub fn to_url<T>(&self, base: T) -> Result<Url> where T: IntoUrl {
         let mut url = base.into_url().map_err(|err| Error::from(ErrorKind::URL(err)))?;
         {
@@ -270,6 +320,10 @@ impl AuthRequest {
                 let s = acr_values.iter().join("%20");
                 pairs.append_pair("acr_values", encode(&s).as_ref());
             }
+            if let (Some(challenge), Some(method)) = (self.code_challenge(), self.code_challenge_method.as_ref()) {
+                pairs.append_pair("code_challenge", challenge.as_ref())
+                    .append_pair("code_challenge_method", method.as_ref());
+            }
         }
 
         Ok(url)
@@ -283,13 +337,21 @@ fn gen_hash() -> ::std::result::Result<String, ErrorKind> {
     }).map_err(ErrorKind::IO)
 }
 
+/// 48 random bytes encode to 64 base64url characters, with no padding to break the verifier.
+#[inline]
+fn gen_verifier() -> ::std::result::Result<String, ErrorKind> {
+    rand::OsRng::new().map(|mut rng| {
+        base64::encode_config(&rng.gen_iter::<u8>().take(48).collect::<Vec<u8>>(), base64::URL_SAFE)
+    }).map_err(ErrorKind::IO)
+}
+
 #[inline]
 fn ser_url<T>(me: &Url, ser: T) -> ::std::result::Result<T::Ok, T::Error> where T: ::serde::Serializer {
     ser.serialize_str(me.as_str())
 }
 
 #[inline]
-fn encode<T>(data: &T) -> String where T: AsRef<[u8]> {
+pub(crate) fn encode<T>(data: &T) -> String where T: AsRef<[u8]> {
     base64::encode_config(data, base64::URL_SAFE)
 }
 
@@ -306,4 +368,32 @@ mod tests {
         assert_eq!("https://google.co.uk/?response_type=code&scope=openid%2520profile&client_id=client_id&redirect_uri=https%3A%2F%2F127.0.0.1%2F&state=VFZWWk5HUnNWbHBqV0ZaRlV6TldiMVZ1Vm5OTlZVcE1Va2N4UmxOck1XWmFNMEY0WVRJeFEyRlVWbmxoTWxaYVRqTndVRTUzUFQwJTNE",
                    req.to_url("https://google.co.uk/").unwrap().as_str());
     }
+
+    #[test]
+    fn test_pkce() {
+        let mut req = AuthRequest::with_state(vec![Scope::Profile], "client_id", "https://127.0.0.1/", "state").unwrap();
+        assert_eq!(None, req.code_challenge());
+
+        // The example from https://tools.ietf.org/html/rfc7636#appendix-B
+        req.set_code_verifier(CodeChallengeMethod::S256, "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk").unwrap();
+        assert_eq!(Some("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_string()), req.code_challenge());
+        assert!(req.to_url("https://google.co.uk/").unwrap().as_str()
+            .ends_with("&code_challenge=E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM&code_challenge_method=S256"));
+
+        req.set_code_verifier(CodeChallengeMethod::Plain, "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk").unwrap();
+        assert_eq!(Some("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string()), req.code_challenge());
+
+        assert!(req.set_code_verifier(CodeChallengeMethod::S256, "too short").is_err());
+        assert!(req.set_code_verifier(CodeChallengeMethod::S256, "a".repeat(129)).is_err());
+    }
+
+    #[test]
+    fn test_gen_verifier() {
+        let mut req = AuthRequest::new(vec![Scope::Profile], "client_id", "https://127.0.0.1/").unwrap();
+        req.set_pkce(CodeChallengeMethod::default()).unwrap();
+        let first = req.code_verifier().unwrap().to_string();
+        assert_eq!(64, first.len());
+        req.set_pkce(CodeChallengeMethod::default()).unwrap();
+        assert_ne!(first, req.code_verifier().unwrap());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,29 @@
+//! The errors which can occur while parsing a response.
+
+use super::AuthError;
+
+error_chain! {
+    foreign_links {
+        Decode(::serde_urlencoded::de::Error);
+    }
+
+    errors {
+        /// The response is missing a parameter the spec requires.
+        MissingParameterError(name: &'static str) {
+            description("Response is missing a required parameter!")
+            display("Response is missing the {} parameter!", name)
+        }
+
+        /// The state of the response is not the state of the request, so it may have been forged
+        /// as described in [the spec](https://tools.ietf.org/html/rfc6749#section-10.12).
+        StateMismatchError {
+            description("Response state does not match the request!")
+        }
+
+        /// The Authorization Server refused the request, as defined by [the spec](https://openid.net/specs/openid-connect-core-1_0.html#AuthError).
+        AuthorizationError(error: AuthError) {
+            description("Authorization Server returned an error!")
+            display("Authorization Server returned an error! {}", error)
+        }
+    }
+}
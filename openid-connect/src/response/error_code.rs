@@ -0,0 +1,111 @@
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+/// The reason the Authorization Server gave for refusing an authentication request, as defined by
+/// [OAuth 2.0](https://tools.ietf.org/html/rfc6749#section-4.1.2.1) and
+/// [the spec](https://openid.net/specs/openid-connect-core-1_0.html#AuthError).
+pub enum ErrorCode {
+    /// The request is missing a parameter, or is otherwise malformed.
+    InvalidRequest,
+    /// The client is not allowed to request an authorization code this way.
+    UnauthorizedClient,
+    /// The End-User or Authorization Server denied the request.
+    AccessDenied,
+    /// The Authorization Server does not support the requested response type.
+    UnsupportedResponseType,
+    /// A requested scope is invalid, unknown or malformed.
+    InvalidScope,
+    /// The Authorization Server failed to handle the request.
+    ServerError,
+    /// The Authorization Server is overloaded or under maintenance.
+    TemporarilyUnavailable,
+    /// The End-User must interact with the Authorization Server, which the prompt forbade.
+    InteractionRequired,
+    /// The End-User must authenticate, which the prompt forbade.
+    LoginRequired,
+    /// The End-User must select an account, which the prompt forbade.
+    AccountSelectionRequired,
+    /// The End-User must consent, which the prompt forbade.
+    ConsentRequired,
+    /// The request_uri parameter is invalid.
+    InvalidRequestUri,
+    /// The request parameter is an invalid Request Object.
+    InvalidRequestObject,
+    /// The Authorization Server does not support the request parameter.
+    RequestNotSupported,
+    /// The Authorization Server does not support the request_uri parameter.
+    RequestUriNotSupported,
+    /// The Authorization Server does not support the registration parameter.
+    RegistrationNotSupported,
+    /// An error code not defined by the specs.
+    Other(String)
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl AsRef<str> for ErrorCode {
+    fn as_ref(&self) -> &str {
+        match *self {
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::UnauthorizedClient => "unauthorized_client",
+            ErrorCode::AccessDenied => "access_denied",
+            ErrorCode::UnsupportedResponseType => "unsupported_response_type",
+            ErrorCode::InvalidScope => "invalid_scope",
+            ErrorCode::ServerError => "server_error",
+            ErrorCode::TemporarilyUnavailable => "temporarily_unavailable",
+            ErrorCode::InteractionRequired => "interaction_required",
+            ErrorCode::LoginRequired => "login_required",
+            ErrorCode::AccountSelectionRequired => "account_selection_required",
+            ErrorCode::ConsentRequired => "consent_required",
+            ErrorCode::InvalidRequestUri => "invalid_request_uri",
+            ErrorCode::InvalidRequestObject => "invalid_request_object",
+            ErrorCode::RequestNotSupported => "request_not_supported",
+            ErrorCode::RequestUriNotSupported => "request_uri_not_supported",
+            ErrorCode::RegistrationNotSupported => "registration_not_supported",
+            ErrorCode::Other(ref s) => s
+        }
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = super::error::Error;
+
+    /// This is case-sensitive to the specs, returning an Other containing a string which is not
+    /// defined in them.
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::str::FromStr;
+    /// # use ::openid_connect::response::ErrorCode;
+    /// assert_eq!(ErrorCode::AccessDenied, ErrorCode::from_str("access_denied").unwrap());
+    /// assert_eq!(ErrorCode::LoginRequired, ErrorCode::from_str("login_required").unwrap());
+    /// assert_eq!(ErrorCode::Other("Access_Denied".to_string()), ErrorCode::from_str("Access_Denied").unwrap());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "invalid_request" => Ok(ErrorCode::InvalidRequest),
+            "unauthorized_client" => Ok(ErrorCode::UnauthorizedClient),
+            "access_denied" => Ok(ErrorCode::AccessDenied),
+            "unsupported_response_type" => Ok(ErrorCode::UnsupportedResponseType),
+            "invalid_scope" => Ok(ErrorCode::InvalidScope),
+            "server_error" => Ok(ErrorCode::ServerError),
+            "temporarily_unavailable" => Ok(ErrorCode::TemporarilyUnavailable),
+            "interaction_required" => Ok(ErrorCode::InteractionRequired),
+            "login_required" => Ok(ErrorCode::LoginRequired),
+            "account_selection_required" => Ok(ErrorCode::AccountSelectionRequired),
+            "consent_required" => Ok(ErrorCode::ConsentRequired),
+            "invalid_request_uri" => Ok(ErrorCode::InvalidRequestUri),
+            "invalid_request_object" => Ok(ErrorCode::InvalidRequestObject),
+            "request_not_supported" => Ok(ErrorCode::RequestNotSupported),
+            "request_uri_not_supported" => Ok(ErrorCode::RequestUriNotSupported),
+            "registration_not_supported" => Ok(ErrorCode::RegistrationNotSupported),
+            _ => Ok(ErrorCode::Other(s.to_string()))
+        }
+    }
+}
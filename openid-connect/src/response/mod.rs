@@ -1,3 +1,256 @@
+//! The redirect the Authorization Server sends back to the client, as defined by
+//! [the spec](https://openid.net/specs/openid-connect-core-1_0.html#AuthResponse).
+
+use std::fmt;
+
+use hyper::Url;
+use serde_urlencoded;
+
+use request::{self, AuthRequest, ResponseType};
+
+mod error_code;
+pub mod error;
+
+pub use self::error_code::ErrorCode;
+use self::error::*;
+
+/// Every parameter either kind of response can carry, before it is known which it is.
+#[derive(Debug, Deserialize)]
+struct Params {
+    code: Option<String>,
+    state: Option<String>,
+    id_token: Option<String>,
+    access_token: Option<String>,
+    session_state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+    error_uri: Option<String>
+}
+
+/// A successful authentication response. A code response carries a `code`, and a token response
+/// an `access_token`.
+#[derive(Debug, Clone, PartialEq)]
 pub struct AuthResponse {
-    
-}
\ No newline at end of file
+    code: Option<String>,
+    state: String,
+    id_token: Option<String>,
+    access_token: Option<String>,
+    session_state: Option<String>
+}
+
+/// An authentication request the Authorization Server refused, as defined by
+/// [the spec](https://openid.net/specs/openid-connect-core-1_0.html#AuthError).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthError {
+    error: ErrorCode,
+    error_description: Option<String>,
+    error_uri: Option<String>,
+    state: String
+}
+
+impl AuthResponse {
+    /// Parses the redirect `url` the Authorization Server sent in answer to `request`, reading the
+    /// parameters from the fragment for a token response and from the query otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate hyper;
+    /// # extern crate openid_connect;
+    /// # use hyper::Url;
+    /// # use openid_connect::request::{AuthRequest, Scope};
+    /// # use openid_connect::response::AuthResponse;
+    /// # fn main() {
+    /// let request = AuthRequest::with_state(vec![Scope::Email], "client_id", "https://127.0.0.1/", "abc").unwrap();
+    ///
+    /// let url = Url::parse("https://127.0.0.1/?code=4%2FP7q7W91&state=YWJj").unwrap();
+    /// let response = AuthResponse::from_url(&url, &request).unwrap();
+    /// assert_eq!(Some("4/P7q7W91"), response.code());
+    /// assert_eq!("abc", response.state());
+    ///
+    /// let forged = Url::parse("https://127.0.0.1/?code=4%2FP7q7W91&state=eHl6").unwrap();
+    /// assert!(AuthResponse::from_url(&forged, &request).is_err());
+    /// # }
+    /// ```
+    pub fn from_url(url: &Url, request: &AuthRequest) -> Result<AuthResponse> {
+        match *request.response_type() {
+            ResponseType::Token => AuthResponse::from_fragment(url.fragment().unwrap_or(""), request),
+            ResponseType::Code => AuthResponse::from_query(url.query().unwrap_or(""), request)
+        }
+    }
+
+    /// Parses the query string of the redirect, with or without its leading `?`.
+    pub fn from_query(query: &str, request: &AuthRequest) -> Result<AuthResponse> {
+        AuthResponse::parse(query.trim_start_matches('?'), request)
+    }
+
+    /// Parses the fragment of the redirect, with or without its leading `#`.
+    pub fn from_fragment(fragment: &str, request: &AuthRequest) -> Result<AuthResponse> {
+        AuthResponse::parse(fragment.trim_start_matches('#'), request)
+    }
+
+    /// Every request is sent with a state, so errors must carry it back like any other response
+    /// and are only believed once it has been checked, so a forged error can't be passed off as
+    /// the Authorization Server's.
+    fn parse(params: &str, request: &AuthRequest) -> Result<AuthResponse> {
+        let params = serde_urlencoded::from_str::<Params>(params).map_err(|err| Error::from(ErrorKind::Decode(err)))?;
+
+        let state = params.state.ok_or_else(|| Error::from(ErrorKind::MissingParameterError("state")))?;
+        check_state(&state, request)?;
+
+        if let Some(error) = params.error {
+            bail!(ErrorKind::AuthorizationError(AuthError {
+                error: error.parse()?,
+                error_description: params.error_description,
+                error_uri: params.error_uri,
+                state: request.state().to_string()
+            }))
+        }
+
+        match *request.response_type() {
+            ResponseType::Code if params.code.is_none() => bail!(ErrorKind::MissingParameterError("code")),
+            ResponseType::Token if params.access_token.is_none() =>
+                bail!(ErrorKind::MissingParameterError("access_token")),
+            _ => {}
+        }
+        Ok(AuthResponse {
+            code: params.code,
+            state: request.state().to_string(),
+            id_token: params.id_token,
+            access_token: params.access_token,
+            session_state: params.session_state
+        })
+    }
+
+    /// The authorization code to exchange for tokens, which only code responses carry.
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_ref().map(String::as_ref)
+    }
+
+    /// The state of the request, which the response has been checked to carry.
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    pub fn id_token(&self) -> Option<&str> {
+        self.id_token.as_ref().map(String::as_ref)
+    }
+
+    pub fn access_token(&self) -> Option<&str> {
+        self.access_token.as_ref().map(String::as_ref)
+    }
+
+    /// The End-User's login state at the Authorization Server, as defined by
+    /// [session management](https://openid.net/specs/openid-connect-session-1_0.html#CreatingUpdatingSessions).
+    pub fn session_state(&self) -> Option<&str> {
+        self.session_state.as_ref().map(String::as_ref)
+    }
+}
+
+impl AuthError {
+    pub fn error(&self) -> &ErrorCode {
+        &self.error
+    }
+
+    pub fn error_description(&self) -> Option<&str> {
+        self.error_description.as_ref().map(String::as_ref)
+    }
+
+    pub fn error_uri(&self) -> Option<&str> {
+        self.error_uri.as_ref().map(String::as_ref)
+    }
+
+    /// The state of the request, which the error has been checked to carry.
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.error_description {
+            Some(ref description) => write!(f, "{}: {}", self.error, description),
+            None => write!(f, "{}", self.error)
+        }
+    }
+}
+
+/// The state is sent encoded by `AuthRequest::to_url`, so it comes back encoded too.
+fn check_state(state: &str, request: &AuthRequest) -> Result<()> {
+    if state == request::encode(&request.state()) {
+        Ok(())
+    } else {
+        Err(Error::from(ErrorKind::StateMismatchError))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use request::Scope;
+
+    fn request() -> AuthRequest {
+        AuthRequest::with_state(vec![Scope::Profile], "client_id", "https://127.0.0.1/", "state").unwrap()
+    }
+
+    #[test]
+    fn test_success_from_query() {
+        let response = AuthResponse::from_query("?code=SplxlOBeZQQYbYS6WxSbIA&state=c3RhdGU%3D&session_state=abc.123",
+                                                &request()).unwrap();
+        assert_eq!(Some("SplxlOBeZQQYbYS6WxSbIA"), response.code());
+        assert_eq!("state", response.state());
+        assert_eq!(Some("abc.123"), response.session_state());
+        assert_eq!(None, response.id_token());
+    }
+
+    #[test]
+    fn test_success_from_fragment() {
+        let mut request = request();
+        request.set_response_type(ResponseType::Token);
+        let url = Url::parse("https://127.0.0.1/#state=c3RhdGU%3D&id_token=eyJ0&access_token=SlAV32").unwrap();
+        let response = AuthResponse::from_url(&url, &request).unwrap();
+        assert_eq!(None, response.code());
+        assert_eq!(Some("eyJ0"), response.id_token());
+        assert_eq!(Some("SlAV32"), response.access_token());
+    }
+
+    #[test]
+    fn test_error() {
+        let err = AuthResponse::from_query("error=access_denied&error_description=The+user+said+no&state=c3RhdGU%3D",
+                                           &request()).unwrap_err();
+        match *err.kind() {
+            ErrorKind::AuthorizationError(ref error) => {
+                assert_eq!(&ErrorCode::AccessDenied, error.error());
+                assert_eq!(Some("The user said no"), error.error_description());
+                assert_eq!("state", error.state());
+            },
+            ref kind => panic!("Expected an authorization error, got {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn test_state_mismatch() {
+        for params in &["code=abc&state=b3RoZXI%3D", "error=access_denied&state=b3RoZXI%3D"] {
+            match *AuthResponse::from_query(params, &request()).unwrap_err().kind() {
+                ErrorKind::StateMismatchError => {},
+                ref kind => panic!("Expected a state mismatch, got {:?}", kind)
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_parameters() {
+        let mut token = request();
+        token.set_response_type(ResponseType::Token);
+        let missing = [("code=abc", request(), "state"),
+                       ("error=access_denied", request(), "state"),
+                       ("state=c3RhdGU%3D", request(), "code"),
+                       ("state=c3RhdGU%3D&code=abc", token, "access_token")];
+        for &(ref params, ref request, name) in &missing {
+            match *AuthResponse::parse(params, request).unwrap_err().kind() {
+                ErrorKind::MissingParameterError(missing) => assert_eq!(name, missing),
+                ref kind => panic!("Expected {} to be missing, got {:?}", name, kind)
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! The errors which can occur while redeeming an authorization code.
+
+use super::TokenError;
+
+error_chain! {
+    foreign_links {
+        IO(::std::io::Error);
+        URL(::hyper::error::ParseError);
+        HTTP(::hyper::error::Error);
+        TLS(::hyper_native_tls::native_tls::Error);
+        JSON(::serde_json::Error);
+        Encode(::serde_urlencoded::ser::Error);
+    }
+
+    errors {
+        /// The token endpoint refused the request, as defined by [the spec](https://tools.ietf.org/html/rfc6749#section-5.2).
+        TokenRequestError(error: TokenError) {
+            description("Token endpoint returned an error!")
+            display("Token endpoint returned an error! {}", error)
+        }
+
+        /// The token endpoint answered with neither tokens nor an error the spec defines.
+        UnexpectedResponseError(status: u16, body: String) {
+            description("Token endpoint returned an unexpected response!")
+            display("Token endpoint returned an unexpected {} response: {}", status, body)
+        }
+
+        /// The authentication response has no code to redeem, as token responses don't.
+        MissingCodeError {
+            description("Authentication response has no authorization code!")
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+/// The reason the token endpoint gave for refusing a token request, as defined by
+/// [OAuth 2.0](https://tools.ietf.org/html/rfc6749#section-5.2).
+pub enum ErrorCode {
+    /// The request is missing a parameter, or is otherwise malformed.
+    InvalidRequest,
+    /// The client could not be authenticated.
+    InvalidClient,
+    /// The authorization code is invalid, expired, revoked, or was issued to another client or
+    /// redirect URI.
+    InvalidGrant,
+    /// The client is not allowed to use this grant type.
+    UnauthorizedClient,
+    /// The Authorization Server does not support this grant type.
+    UnsupportedGrantType,
+    /// A requested scope is invalid, unknown or malformed.
+    InvalidScope,
+    /// An error code not defined by the spec.
+    Other(String)
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl AsRef<str> for ErrorCode {
+    fn as_ref(&self) -> &str {
+        match *self {
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::InvalidClient => "invalid_client",
+            ErrorCode::InvalidGrant => "invalid_grant",
+            ErrorCode::UnauthorizedClient => "unauthorized_client",
+            ErrorCode::UnsupportedGrantType => "unsupported_grant_type",
+            ErrorCode::InvalidScope => "invalid_scope",
+            ErrorCode::Other(ref s) => s
+        }
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = super::error::Error;
+
+    /// This is case-sensitive to [the spec](https://tools.ietf.org/html/rfc6749#section-5.2),
+    /// returning an Other containing a string which is not defined in it.
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::str::FromStr;
+    /// # use ::openid_connect::token::ErrorCode;
+    /// assert_eq!(ErrorCode::InvalidGrant, ErrorCode::from_str("invalid_grant").unwrap());
+    /// assert_eq!(ErrorCode::InvalidClient, ErrorCode::from_str("invalid_client").unwrap());
+    /// assert_eq!(ErrorCode::Other("slow_down".to_string()), ErrorCode::from_str("slow_down").unwrap());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "invalid_request" => Ok(ErrorCode::InvalidRequest),
+            "invalid_client" => Ok(ErrorCode::InvalidClient),
+            "invalid_grant" => Ok(ErrorCode::InvalidGrant),
+            "unauthorized_client" => Ok(ErrorCode::UnauthorizedClient),
+            "unsupported_grant_type" => Ok(ErrorCode::UnsupportedGrantType),
+            "invalid_scope" => Ok(ErrorCode::InvalidScope),
+            _ => Ok(ErrorCode::Other(s.to_string()))
+        }
+    }
+}
@@ -1,3 +1,13 @@
+DROP TABLE limit_overrides;
+DROP TABLE exchange_rates;
+DROP TABLE instalments;
+DROP TABLE repayments;
+DROP TABLE request_events;
+DROP TRIGGER requests_status_transition ON requests;
+DROP FUNCTION check_request_transition();
 DROP TABLE requests;
+DROP TABLE request_transitions;
+DROP TABLE personal_tokens;
 DROP TABLE tokens;
+DROP TABLE user_roles;
 DROP TABLE users;
\ No newline at end of file
@@ -5,13 +5,135 @@ CREATE TABLE users (
   email VARCHAR NOT NULL
 );
 
+-- Must be kept in step with roles::Role
+CREATE TABLE user_roles (
+  user_id VARCHAR REFERENCES users(id) NOT NULL,
+  role VARCHAR NOT NULL CHECK (role IN ('admin', 'borrower', 'auditor')),
+  granted_by VARCHAR REFERENCES users(id),
+  granted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (user_id, role)
+);
+
+CREATE TABLE request_transitions (
+  from_status VARCHAR NOT NULL,
+  to_status VARCHAR NOT NULL,
+  PRIMARY KEY (from_status, to_status)
+);
+
+-- The same pairs as lifecycle::Status::can_transition, which its tests check
+INSERT INTO request_transitions (from_status, to_status) VALUES
+  ('pending', 'approved'),
+  ('pending', 'rejected'),
+  ('pending', 'cancelled'),
+  ('pending', 'expired'),
+  ('approved', 'disbursed'),
+  ('approved', 'expired'),
+  ('disbursed', 'repaid'),
+  ('disbursed', 'written_off');
+
 CREATE TABLE requests (
   id SERIAL PRIMARY KEY,
   user_id VARCHAR references users(id) NOT NULL,
-  amount INTEGER NOT NULL
+  amount BIGINT NOT NULL CHECK (amount > 0),
+  currency VARCHAR(3) NOT NULL DEFAULT 'GBP' CHECK (currency ~ '^[A-Z]{3}$'),
+  status VARCHAR NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected', 'disbursed',
+                                                              'repaid', 'written_off', 'cancelled', 'expired')),
+  interest_method VARCHAR NOT NULL DEFAULT 'simple' CHECK (interest_method IN ('simple', 'daily_compound')),
+  interest_rate_bps INTEGER NOT NULL DEFAULT 0 CHECK (interest_rate_bps >= 0),
+  disbursed_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
 );
 
-CREATE TABLE tokens (
+CREATE FUNCTION check_request_transition() RETURNS TRIGGER AS $$
+BEGIN
+  IF NEW.status <> OLD.status AND NOT EXISTS (
+    SELECT 1 FROM request_transitions WHERE from_status = OLD.status AND to_status = NEW.status
+  ) THEN
+    RAISE EXCEPTION 'Illegal request transition from % to %', OLD.status, NEW.status;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER requests_status_transition BEFORE UPDATE OF status ON requests
+  FOR EACH ROW EXECUTE PROCEDURE check_request_transition();
+
+CREATE TABLE repayments (
+  id SERIAL PRIMARY KEY,
+  request_id INTEGER REFERENCES requests(id) NOT NULL,
+  amount BIGINT NOT NULL CHECK (amount > 0),
+  recorded_by VARCHAR REFERENCES users(id) NOT NULL,
+  paid_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE instalments (
+  id SERIAL PRIMARY KEY,
+  request_id INTEGER REFERENCES requests(id) NOT NULL,
+  number INTEGER NOT NULL CHECK (number > 0),
+  due_date DATE NOT NULL,
+  amount BIGINT NOT NULL CHECK (amount > 0),
+  UNIQUE (request_id, number)
+);
+
+CREATE TABLE request_events (
+  id SERIAL PRIMARY KEY,
+  request_id INTEGER REFERENCES requests(id) NOT NULL,
+  kind VARCHAR NOT NULL,
+  actor VARCHAR REFERENCES users(id) NOT NULL,
+  amount BIGINT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Units of the home currency per unit of currency, in millionths
+CREATE TABLE exchange_rates (
+  currency VARCHAR(3) NOT NULL CHECK (currency ~ '^[A-Z]{3}$'),
+  effective_date DATE NOT NULL,
+  rate_micros BIGINT NOT NULL CHECK (rate_micros > 0),
+  updated_by VARCHAR REFERENCES users(id) NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (currency, effective_date)
+);
+
+-- Per-borrower replacements for the [policy] limits in config.toml, NULL keeps the default
+CREATE TABLE limit_overrides (
   user_id VARCHAR PRIMARY KEY REFERENCES users(id),
-  token VARCHAR NOT NULL
-)
\ No newline at end of file
+  max_outstanding BIGINT CHECK (max_outstanding > 0),
+  max_request BIGINT CHECK (max_request > 0),
+  max_open_loans INTEGER CHECK (max_open_loans >= 0),
+  cooling_off_days INTEGER CHECK (cooling_off_days >= 0),
+  updated_by VARCHAR REFERENCES users(id) NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- One row per session. Only the SHA-256 hash of the current refresh token is kept.
+CREATE TABLE tokens (
+  id SERIAL PRIMARY KEY,
+  user_id VARCHAR REFERENCES users(id) NOT NULL,
+  token_hash VARCHAR(64) NOT NULL UNIQUE,
+  expires_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  refreshed_at TIMESTAMPTZ,
+  last_used_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  ip VARCHAR NOT NULL,
+  user_agent VARCHAR,
+  revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX tokens_user_id ON tokens (user_id);
+
+-- Only the SHA-256 hash of each token is kept. Scopes must be kept in step with personal_tokens::Scope
+CREATE TABLE personal_tokens (
+  id SERIAL PRIMARY KEY,
+  user_id VARCHAR REFERENCES users(id) NOT NULL,
+  name VARCHAR NOT NULL,
+  token_hash VARCHAR(64) NOT NULL UNIQUE,
+  scopes VARCHAR[] NOT NULL CHECK (cardinality(scopes) > 0 AND
+                                   scopes <@ ARRAY['requests:read', 'requests:write']::VARCHAR[]),
+  expires_on DATE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  last_used_at TIMESTAMPTZ,
+  revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX personal_tokens_user_id ON personal_tokens (user_id);
\ No newline at end of file
@@ -0,0 +1,303 @@
+//! ISO-4217 currencies and the exchange rates used to report amounts in the home currency.
+
+use std::fmt;
+use std::str::FromStr;
+use std::collections::HashMap;
+use std::error::Error as StdError;
+
+use chrono::NaiveDate;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+use postgres::GenericConnection;
+use postgres::types::{ToSql, FromSql, Type, IsNull};
+use config::Config;
+
+use errors::*;
+use money::Money;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub fn from_config(config: &Config) -> Result<Currency> {
+        let table = match config.get_table("currency") {
+            Ok(table) => table,
+            Err(_) => return Ok(Currency::default())
+        };
+
+        match table.get("home") {
+            Some(v) => v.clone().into_str()
+                .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?
+                .parse::<Currency>()
+                .map_err(|_| Error::from(ErrorKind::InvalidConfigTypeError("currency.home".to_string(),
+                                                                            "ISO-4217 currency code".to_string()))),
+            None => Ok(Currency::default())
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        ::std::str::from_utf8(&self.0).expect("currency codes are ASCII")
+    }
+
+    /// The symbol amounts in this currency may be written with, if it has one.
+    pub fn symbol(&self) -> Option<char> {
+        match self.code() {
+            "GBP" => Some('£'),
+            "USD" => Some('$'),
+            "EUR" => Some('€'),
+            _ => None
+        }
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Currency {
+        Currency(*b"GBP")
+    }
+}
+
+impl FromStr for Currency {
+    type Err = Error;
+
+    /// Parses a three letter ISO-4217 code, ignoring case.
+    fn from_str(s: &str) -> Result<Currency> {
+        let code = s.trim().to_uppercase();
+        let bytes = code.as_bytes();
+        if bytes.len() != 3 || !bytes.iter().all(|&b| b >= b'A' && b <= b'Z') {
+            bail!(ErrorKind::CurrencyParseError(s.to_string()))
+        }
+        Ok(Currency([bytes[0], bytes[1], bytes[2]]))
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Currency, D::Error> where D: Deserializer<'de> {
+        struct CurrencyVisitor;
+
+        impl<'de> Visitor<'de> for CurrencyVisitor {
+            type Value = Currency;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an ISO-4217 currency code, such as \"GBP\"")
+            }
+
+            fn visit_str<E>(self, s: &str) -> ::std::result::Result<Currency, E> where E: de::Error {
+                s.parse().map_err(|err: Error| E::custom(err))
+            }
+        }
+
+        deserializer.deserialize_str(CurrencyVisitor)
+    }
+}
+
+impl ToSql for Currency {
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> ::std::result::Result<IsNull, Box<StdError + Sync + Send>> {
+        self.code().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(&self, ty: &Type, out: &mut Vec<u8>) -> ::std::result::Result<IsNull, Box<StdError + Sync + Send>> {
+        self.code().to_sql_checked(ty, out)
+    }
+}
+
+impl FromSql for Currency {
+    fn from_sql(ty: &Type, raw: &[u8]) -> ::std::result::Result<Currency, Box<StdError + Sync + Send>> {
+        String::from_sql(ty, raw)
+            .and_then(|code| code.parse().map_err(|err: Error| err.to_string().into()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+/// Units of the home currency per unit of another currency, in millionths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate(i64);
+
+impl Rate {
+    const SCALE: i64 = 1_000_000;
+    const MAX: i64 = 1_000 * Rate::SCALE;
+
+    pub fn identity() -> Rate {
+        Rate(Rate::SCALE)
+    }
+
+    pub fn from_micros(micros: i64) -> Rate {
+        Rate(micros)
+    }
+
+    pub fn micros(&self) -> i64 {
+        self.0
+    }
+
+    /// Converts `money` into the home currency, rounding half up to the nearest minor unit.
+    pub fn convert(&self, money: Money) -> Money {
+        let units = money.minor_units() * self.0;
+        let rounded = if units >= 0 {
+            (units + Rate::SCALE / 2) / Rate::SCALE
+        } else {
+            (units - Rate::SCALE / 2) / Rate::SCALE
+        };
+        Money::from_minor_units(rounded)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = Error;
+
+    /// Parses a positive decimal rate with at most six decimal places, such as `1.17` or `0.852`.
+    fn from_str(s: &str) -> Result<Rate> {
+        let s = s.trim();
+        let (whole, fraction) = match s.find('.') {
+            Some(point) => (&s[..point], &s[point + 1..]),
+            None => (s, "")
+        };
+        if whole.is_empty() || whole.len() > 4 || fraction.len() > 6 || (s.contains('.') && fraction.is_empty()) ||
+            !whole.chars().chain(fraction.chars()).all(|c| c.is_digit(10)) {
+            bail!(ErrorKind::RateParseError(s.to_string()))
+        }
+
+        let micros = whole.chars()
+            .chain(fraction.chars())
+            .chain(::std::iter::repeat('0').take(6 - fraction.len()))
+            .fold(0i64, |acc, c| acc * 10 + i64::from(c.to_digit(10).unwrap_or(0)));
+        if micros == 0 || micros > Rate::MAX {
+            bail!(ErrorKind::RateParseError(s.to_string()))
+        }
+        Ok(Rate(micros))
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:06}", self.0 / Rate::SCALE, self.0 % Rate::SCALE)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> where S: Serializer {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Rate, D::Error> where D: Deserializer<'de> {
+        struct RateVisitor;
+
+        impl<'de> Visitor<'de> for RateVisitor {
+            type Value = Rate;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an exchange rate as a string, such as \"1.17\"")
+            }
+
+            fn visit_str<E>(self, s: &str) -> ::std::result::Result<Rate, E> where E: de::Error {
+                s.parse().map_err(|err: Error| E::custom(err))
+            }
+        }
+
+        deserializer.deserialize_str(RateVisitor)
+    }
+}
+
+/// Looks up the rates that applied on each date, remembering them for the life of the lookup.
+pub struct Rates<'a, C: 'a + GenericConnection> {
+    con: &'a C,
+    home: Currency,
+    cache: HashMap<(Currency, NaiveDate), Rate>
+}
+
+impl<'a, C: GenericConnection> Rates<'a, C> {
+    pub fn new(con: &'a C, home: Currency) -> Rates<'a, C> {
+        Rates {
+            con,
+            home,
+            cache: HashMap::new()
+        }
+    }
+
+    pub fn home(&self) -> Currency {
+        self.home
+    }
+
+    /// The most recent rate for `currency` taking effect on or before `date`.
+    pub fn on(&mut self, currency: Currency, date: NaiveDate) -> Result<Rate> {
+        if currency == self.home {
+            return Ok(Rate::identity())
+        }
+        if let Some(rate) = self.cache.get(&(currency, date)) {
+            return Ok(*rate)
+        }
+
+        let rate = self.con.query("SELECT rate_micros FROM exchange_rates WHERE currency = $1 AND effective_date <= $2 \
+                                   ORDER BY effective_date DESC LIMIT 1;", &[&currency, &date])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))?
+            .iter()
+            .next()
+            .map(|row| Rate(row.get("rate_micros")))
+            .ok_or_else(|| Error::from(ErrorKind::MissingExchangeRateError(currency.to_string(), date.to_string())))?;
+        self.cache.insert((currency, date), rate);
+        Ok(rate)
+    }
+
+    pub fn convert(&mut self, money: Money, currency: Currency, date: NaiveDate) -> Result<Money> {
+        self.on(currency, date).map(|rate| rate.convert(money))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_currency_codes() {
+        assert_eq!("EUR", "eur".parse::<Currency>().unwrap().code());
+        assert_eq!("USD", " USD ".parse::<Currency>().unwrap().code());
+        assert!("EU".parse::<Currency>().is_err());
+        assert!("EURO".parse::<Currency>().is_err());
+        assert!("E1R".parse::<Currency>().is_err());
+        assert!("€".parse::<Currency>().is_err());
+    }
+
+    #[test]
+    fn parses_rates_exactly() {
+        assert_eq!(Some(1_170_000), "1.17".parse::<Rate>().ok().map(|rate| rate.micros()));
+        assert_eq!(Some(852_000), "0.852".parse::<Rate>().ok().map(|rate| rate.micros()));
+        assert_eq!(Some(2_000_000), "2".parse::<Rate>().ok().map(|rate| rate.micros()));
+        assert_eq!(Some(1), "0.000001".parse::<Rate>().ok().map(|rate| rate.micros()));
+        assert!("0".parse::<Rate>().is_err());
+        assert!("-1.2".parse::<Rate>().is_err());
+        assert!("1.".parse::<Rate>().is_err());
+        assert!("0.0000001".parse::<Rate>().is_err());
+        assert!("1000.000001".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn converts_rounding_half_up() {
+        let rate = Rate::from_micros(852_000);
+        // 1000 * 0.852 = 852p exactly
+        assert_eq!(852, rate.convert(Money::from_minor_units(1000)).minor_units());
+        // 1 * 0.5 = 0.5p rounds up, 1 * 0.499999 rounds down
+        assert_eq!(1, Rate::from_micros(500_000).convert(Money::from_minor_units(1)).minor_units());
+        assert_eq!(0, Rate::from_micros(499_999).convert(Money::from_minor_units(1)).minor_units());
+        assert_eq!(1234, Rate::identity().convert(Money::from_minor_units(1234)).minor_units());
+        assert_eq!("0.852000", rate.to_string());
+    }
+}
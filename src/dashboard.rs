@@ -0,0 +1,123 @@
+use iron::prelude::*;
+use iron::Handler;
+use iron::method::Method;
+use iron::status;
+use postgres::Connection;
+use chrono::Utc;
+
+use errors::*;
+use providers::Database;
+use models;
+use request::repayment;
+use json;
+use params::{self, query_map, caller, single_value};
+use currency::{Currency, Rates};
+use money::Money;
+use lifecycle::Status;
+
+pub struct DashboardHandler {
+    home: Currency
+}
+
+/// Totals across every request visible to the caller, converted to the home currency. Amounts
+/// lent use the rate on the day of disbursal, repayments the rate on the day they were paid, and
+/// balances the latest rate.
+#[derive(Debug, Serialize)]
+struct Totals {
+    currency: Currency,
+    requests: usize,
+    requested: Money,
+    lent: Money,
+    repaid: Money,
+    accrued_interest: Money,
+    outstanding: Money
+}
+
+impl DashboardHandler {
+    pub fn new(home: Currency) -> DashboardHandler {
+        DashboardHandler {
+            home
+        }
+    }
+
+    fn totals(&self, req: &mut Request) -> Result<Response> {
+        let caller = caller(req)?;
+        let mut map = query_map(req)?;
+        let user = single_value(&mut map, "user").chain_err(|| ErrorKind::BadRequestError)?;
+        let user = if caller.can_view_all() {
+            user
+        } else {
+            match user {
+                Some(ref user) if *user != caller.subject => bail!(ErrorKind::ForbiddenError),
+                _ => Some(caller.subject)
+            }
+        };
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let totals = totals(&con, self.home, user)?;
+        json::response(status::Ok, &totals)
+    }
+}
+
+fn totals(con: &Connection, home: Currency, user: Option<String>) -> Result<Totals> {
+    let requests = con.query(&format!("SELECT {} FROM requests WHERE $1::VARCHAR IS NULL OR user_id = $1;",
+                                      models::REQUEST_COLUMNS), &[&user])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?
+        .iter()
+        .map(|row| models::Request::from_row(&row))
+        .collect::<Result<Vec<models::Request>>>()?;
+
+    let ids = requests.iter().map(|request| request.id).collect::<Vec<i32>>();
+    let repayments = repayment::by_request(con, &ids)?;
+
+    let today = Utc::today().naive_utc();
+    let mut rates = Rates::new(con, home);
+    let mut totals = Totals {
+        currency: home,
+        requests: requests.len(),
+        requested: Money::zero(),
+        lent: Money::zero(),
+        repaid: Money::zero(),
+        accrued_interest: Money::zero(),
+        outstanding: Money::zero()
+    };
+
+    for request in &requests {
+        let paid = repayments.get(&request.id).map(Vec::as_slice).unwrap_or(&[]);
+        totals.requested = totals.requested +
+            rates.convert(request.amount, request.currency, request.created_at.naive_utc().date())?;
+
+        let disbursed_at = match request.disbursed_at {
+            Some(disbursed_at) => disbursed_at,
+            None => continue
+        };
+        totals.lent = totals.lent + rates.convert(request.amount, request.currency, disbursed_at.naive_utc().date())?;
+        for repayment in paid {
+            totals.repaid = totals.repaid +
+                rates.convert(repayment.amount, request.currency, repayment.paid_at.naive_utc().date())?;
+        }
+
+        let balance = request.balance(paid);
+        totals.accrued_interest = totals.accrued_interest +
+            rates.convert(balance.accrued_interest, request.currency, today)?;
+        if request.status == Status::Disbursed {
+            totals.outstanding = totals.outstanding + rates.convert(balance.total_owed, request.currency, today)?;
+        }
+    }
+
+    Ok(totals)
+}
+
+impl Handler for DashboardHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = params::path(req);
+
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Get, 0) => self.totals(req),
+            (_, 0) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support GET!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+}
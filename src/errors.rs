@@ -48,6 +48,11 @@ error_chain! {
             display("There can on be {} of the {} data!", count, data)
         }
 
+        InvalidRequestDataError(data: String) {
+            description("Your request has some invalid data!")
+            display("The {} data in your request is invalid!", data)
+        }
+
         MissingRequestError {
             description("No request data given!")
         }
@@ -60,10 +65,120 @@ error_chain! {
             description("Your request was invalid!")
         }
 
+        UnauthorizedError {
+            description("You need to sign in to do that!")
+        }
+
+        ForbiddenError {
+            description("You are not allowed to do that!")
+        }
+
+        MissingCredentialsError {
+            description("No Authorization header given!")
+        }
+
+        MalformedCredentialsError {
+            description("Authorization header must hold a bearer token!")
+        }
+
+        RegistrationClosedError(email: String) {
+            description("Sign up is closed to you!")
+            display("{} is not allowed to sign up!", email)
+        }
+
+        LastAdminError {
+            description("The last admin can not stop being an admin!")
+        }
+
+        InvalidPersonalTokenError {
+            description("Personal token is unknown, revoked or has expired!")
+        }
+
+        InsufficientScopeError(scope: String) {
+            description("Token does not have the scope needed!")
+            display("A token with the {} scope is needed!", scope)
+        }
+
+        PersonalTokenNotAllowedError {
+            description("Personal tokens can not be used here!")
+        }
+
+        RevokedSessionError {
+            description("Session has been revoked!")
+        }
+
+        InvalidRefreshTokenError {
+            description("Refresh token is unknown or has expired!")
+        }
+
+        InvalidColumnError(column: String, value: String) {
+            description("Database holds a value that can not be read!")
+            display("The {} column holds {}, which can not be read!", column, value)
+        }
+
+        InvalidTransitionError(from: String, to: String) {
+            description("Request can not move to that status!")
+            display("A request can not move from {} to {}!", from, to)
+        }
+
+        NotRepayableError(status: String) {
+            description("Request can not be repaid!")
+            display("A {} request can not be repaid!", status)
+        }
+
+        OverpaymentError(amount: ::money::Money, outstanding: ::money::Money) {
+            description("Repayment is more than the outstanding balance!")
+            display("A repayment of {} is more than the outstanding balance of {}!", amount, outstanding)
+        }
+
+        LendingPolicyError(rule: String, reason: String) {
+            description("Request breaks the lending policy!")
+            display("The request breaks the {} rule: {}!", rule, reason)
+        }
+
+        NotFoundError(what: String) {
+            description("Resource not found!")
+            display("Could not find {}!", what)
+        }
+
         AmountParseError {
             description("Invalid amount given!")
         }
 
+        AmountNotPositiveError {
+            description("Amount must be more than zero!")
+        }
+
+        AmountTooLargeError(max: String) {
+            description("Amount is too large!")
+            display("Amounts can not be more than {}!", max)
+        }
+
+        CurrencyParseError(code: String) {
+            description("Invalid currency given!")
+            display("{} is not an ISO-4217 currency code!", code)
+        }
+
+        CurrencyMismatchError(given: String, expected: String) {
+            description("Wrong currency given!")
+            display("Expected an amount in {} but was given {}!", expected, given)
+        }
+
+        RateParseError(rate: String) {
+            description("Invalid exchange rate given!")
+            display("{} is not a valid exchange rate!", rate)
+        }
+
+        MissingExchangeRateError(currency: String, date: String) {
+            description("No exchange rate available!")
+            display("There is no exchange rate for {} on or before {}!", currency, date)
+        }
+
+        UnsupportedMediaTypeError(mime: String) {
+            description("Unsupported request body type!")
+            display("Request bodies of type {} are not supported!", mime)
+        }
+
         MissingDatabaseConnectionError {
             description("No connection to the database found!")
         }
@@ -76,6 +191,38 @@ error_chain! {
             description("No matching key for Google's JWT!")
         }
 
+        UnsupportedAlgorithmError(alg: String) {
+            description("Token is signed with an unsupported algorithm!")
+            display("Tokens signed with {} are not accepted!", alg)
+        }
+
+        InvalidIssuerError(iss: String) {
+            description("Token was issued by an unknown issuer!")
+            display("Tokens issued by {} are not accepted!", iss)
+        }
+
+        InvalidAudienceError {
+            description("Token was not issued to this application!")
+        }
+
+        ExpiredTokenError {
+            description("Token has expired!")
+        }
+
+        TokenNotYetValidError {
+            description("Token was issued in the future!")
+        }
+
+        UnverifiedEmailError(email: String) {
+            description("Email address has not been verified!")
+            display("The email address {} has not been verified!", email)
+        }
+
+        EmailDomainNotAllowedError(email: String) {
+            description("Email address is not in an allowed domain!")
+            display("The email address {} is not in a domain this provider may sign in!", email)
+        }
+
         PoisonError(msg: String, obj: String) {
             description("Read Write Lock was poisoned!")
             display("The Read Write Lock for {} was poisoned! {}", obj, msg)
@@ -0,0 +1,120 @@
+use iron::prelude::*;
+use iron::Handler;
+use iron::method::Method;
+use iron::status;
+use urlencoded::QueryResult;
+
+use errors::*;
+use providers::Database;
+use models::{self, ExchangeRate, NewExchangeRate};
+use json;
+use params::{self, body, query_map, caller, single_value, required_value};
+use currency::Currency;
+
+pub struct ExchangeRateHandler {
+    home: Currency
+}
+
+#[derive(Debug, Serialize)]
+struct Rates {
+    home: Currency,
+    rates: Vec<ExchangeRate>
+}
+
+impl NewExchangeRate {
+    fn from_query(map_res: QueryResult) -> Result<NewExchangeRate> {
+        let mut map = map_res.map_err(|err| Error::from(ErrorKind::RequestDecodeError(err)))
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let currency = required_value(&mut map, "currency")
+            .and_then(|currency| currency.parse())
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let effective_date = required_value(&mut map, "effective_date")
+            .and_then(|effective_date| params::date("effective_date", &effective_date))
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let rate = required_value(&mut map, "rate")
+            .and_then(|rate| rate.parse())
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        Ok(NewExchangeRate {
+            currency,
+            effective_date,
+            rate
+        })
+    }
+}
+
+impl ExchangeRateHandler {
+    pub fn new(home: Currency) -> ExchangeRateHandler {
+        ExchangeRateHandler {
+            home
+        }
+    }
+
+    fn list(&self, req: &mut Request) -> Result<Response> {
+        caller(req)?;
+        let mut map = query_map(req)?;
+        let currency = match single_value(&mut map, "currency").chain_err(|| ErrorKind::BadRequestError)? {
+            Some(currency) => Some(currency.parse::<Currency>().chain_err(|| ErrorKind::BadRequestError)?),
+            None => None
+        };
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let rates = con.query(&format!("SELECT {} FROM exchange_rates WHERE $1::VARCHAR IS NULL OR currency = $1 \
+                                        ORDER BY currency, effective_date DESC;", models::EXCHANGE_RATE_COLUMNS),
+                              &[&currency])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?
+            .iter()
+            .map(|row| ExchangeRate::from_row(&row))
+            .collect();
+
+        json::response(status::Ok, &Rates {
+            home: self.home,
+            rates
+        })
+    }
+
+    fn set(&self, req: &mut Request) -> Result<Response> {
+        let caller = caller(req)?;
+        if !caller.is_admin() {
+            bail!(ErrorKind::ForbiddenError)
+        }
+        let rate = body(req, NewExchangeRate::from_query)?;
+        if rate.currency == self.home {
+            bail!(ErrorKind::InvalidRequestDataError("currency".to_string()))
+        }
+
+        // Setting a rate for a date that already has one corrects it.
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let saved = con.query(&format!("INSERT INTO exchange_rates (currency, effective_date, rate_micros, updated_by) \
+                                        VALUES ($1, $2, $3, $4) ON CONFLICT (currency, effective_date) DO UPDATE \
+                                        SET rate_micros = EXCLUDED.rate_micros, updated_by = EXCLUDED.updated_by, \
+                                        updated_at = now() RETURNING {};", models::EXCHANGE_RATE_COLUMNS),
+                              &[&rate.currency, &rate.effective_date, &rate.rate.micros(), &caller.subject])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?
+            .iter()
+            .next()
+            .map(|row| ExchangeRate::from_row(&row))
+            .ok_or_else(|| Error::from(ErrorKind::InternalServerError))?;
+
+        json::response(status::Ok, &saved)
+    }
+}
+
+impl Handler for ExchangeRateHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = params::path(req);
+
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Get, 0) => self.list(req),
+            (Method::Post, 0) => self.set(req),
+            (_, 0) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support GET and POST!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+}
@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use hyper::{Client, header};
 use serde_json;
@@ -7,41 +7,56 @@ use chrono::Duration;
 
 use super::error::*;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Discovery {
     pub issuer: String,
     pub authorization_endpoint: String,
     pub token_endpoint: String,
-    pub userinfo_endpoint: String,
-    pub revocation_endpoint: String,
+    pub userinfo_endpoint: Option<String>,
+    pub revocation_endpoint: Option<String>,
     pub jwks_uri: String,
     pub response_types_supported: Vec<String>,
     pub subject_types_supported: Vec<String>,
     pub id_token_signing_alg_values_supported: Vec<String>,
+    // Everything below is optional, and not every provider publishes it.
+    #[serde(default)]
     pub token_endpoint_auth_methods_supported: Vec<String>,
+    #[serde(default)]
     pub claims_supported: Vec<String>,
+    #[serde(default)]
     pub code_challenge_methods_supported: Vec<String>
 }
 
-#[derive(Deserialize, Debug)]
+/// Where `issuer` publishes its discovery document.
+pub fn discovery_url(issuer: &str) -> String {
+    format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct CachedDiscovery {
+    issuer: String,
     discovery: Discovery,
     expires: DateTime<Utc>,
 }
 
 impl CachedDiscovery {
-    pub fn new(client: &Client) -> Result<CachedDiscovery> {
-        client.get("https://accounts.google.com/.well-known/openid-configuration")
+    pub fn new(client: &Client, issuer: &str) -> Result<CachedDiscovery> {
+        client.get(&discovery_url(issuer))
             .send()
             .map_err(ErrorKind::HyperError)
             .and_then(|mut response| {
-                println!("Headers: {}", response.headers);
                 let mut s = String::new();
                 response.read_to_string(&mut s)
                     .map_err(ErrorKind::IoError)
                     .and_then(|_| serde_json::from_str::<Discovery>(&s)
                         .map_err(ErrorKind::JsonError)
+                        .and_then(|discovery| if discovery.issuer == issuer {
+                            Ok(discovery)
+                        } else {
+                            Err(ErrorKind::IssuerMismatchError(issuer.to_string(), discovery.issuer))
+                        })
                         .map(|discovery| CachedDiscovery {
+                            issuer: issuer.to_string(),
                             discovery,
                             expires: Utc::now() +
                                 Duration::seconds(response.headers.get::<header::CacheControl>()
@@ -59,24 +74,23 @@ impl CachedDiscovery {
         serde_json::from_reader(read).map_err(|err| ErrorKind::JsonError(err).into())
     }
 
-    pub fn discovery(&mut self, client: &Client) -> Result<&Discovery> {
-        if self.is_expired() {
-            CachedDiscovery::new(client).map(move |disc| {
-                self.discovery = disc.discovery;
-                self.expires = disc.expires;
-                &self.discovery
-            })
-        } else {
-            Ok(&self.discovery)
-        }
+    /// Writes the document and its expiry in the form `from_cache` reads.
+    pub fn save<T: Write>(&self, write: T) -> Result<()> {
+        serde_json::to_writer(write, self).map_err(|err| ErrorKind::JsonError(err).into())
+    }
+
+    /// The cached document, even if it has expired.
+    pub fn current(&self) -> &Discovery {
+        &self.discovery
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
     }
 
-    pub fn discovery_opt(&self) -> Option<&Discovery> {
-        if self.is_expired() {
-            None
-        } else {
-            Some(&self.discovery)
-        }
+    /// Whether the cache will have expired `duration` from now.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        Utc::now() + duration >= self.expires
     }
 
     pub fn is_expired(&self) -> bool {
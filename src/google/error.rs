@@ -4,5 +4,18 @@ error_chain! {
         HyperError(::hyper::error::Error);
         JsonError(::serde_json::Error);
         IoError(::std::io::Error);
+        Base64Error(::base64::DecodeError);
     }
-}
\ No newline at end of file
+
+    errors {
+        UnsupportedKeyError(kid: String, kty: String) {
+            description("Key is not an RSA key!")
+            display("The key {} has type {} but only RSA keys are supported!", kid, kty)
+        }
+
+        IssuerMismatchError(expected: String, found: String) {
+            description("Discovery document is for another issuer!")
+            display("The discovery document of {} is for the issuer {}!", expected, found)
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use hyper::{header, Client};
 use serde_json;
 use chrono::{DateTime, Utc, Duration};
+use base64;
 
 use super::error::*;
 use super::discovery::Discovery;
@@ -12,18 +13,72 @@ struct Keys {
     keys: Vec<Key>
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Key {
     pub kty: String,
-    pub alg: String,
+    pub alg: Option<String>,
     #[serde(rename="use")]
-    pub use_on: String,
+    pub use_on: Option<String>,
     pub kid: String,
     pub n: String,
     pub e: String
 }
 
-#[derive(Deserialize, Debug)]
+impl Key {
+    /// The key as a DER encoded PKCS#1 `RSAPublicKey`, built from its modulus and exponent.
+    pub fn public_key_der(&self) -> Result<Vec<u8>> {
+        if self.kty != "RSA" {
+            bail!(ErrorKind::UnsupportedKeyError(self.kid.clone(), self.kty.clone()))
+        }
+        let n = decode_url_safe(&self.n)?;
+        let e = decode_url_safe(&self.e)?;
+
+        let mut body = Vec::new();
+        der_integer(&n, &mut body);
+        der_integer(&e, &mut body);
+        let mut der = vec![0x30];
+        der_length(body.len(), &mut der);
+        der.extend(body);
+        Ok(der)
+    }
+}
+
+/// Decodes unpadded base64url, as used by JWKs and JWTs.
+pub fn decode_url_safe(s: &str) -> Result<Vec<u8>> {
+    let mut padded = s.to_string();
+    while padded.len() % 4 != 0 {
+        padded.push('=');
+    }
+    base64::decode_config(&padded, base64::URL_SAFE).map_err(|err| ErrorKind::Base64Error(err).into())
+}
+
+// An unsigned big-endian integer, with a leading zero added if the top bit is set so it stays positive.
+fn der_integer(bytes: &[u8], out: &mut Vec<u8>) {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    let bytes = &bytes[start..];
+    let pad = bytes.is_empty() || bytes[0] & 0x80 != 0;
+    out.push(0x02);
+    der_length(bytes.len() + if pad { 1 } else { 0 }, out);
+    if pad {
+        out.push(0);
+    }
+    out.extend_from_slice(bytes);
+}
+
+fn der_length(len: usize, out: &mut Vec<u8>) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = (0..8).rev()
+            .map(|i| (len >> (i * 8)) as u8)
+            .skip_while(|&b| b == 0)
+            .collect::<Vec<u8>>();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct CachedKeys {
     keys: Vec<Key>,
     expiry: DateTime<Utc>
@@ -58,35 +113,68 @@ impl CachedKeys {
         serde_json::from_reader(read).map_err(|err| ErrorKind::JsonError(err).into())
     }
 
-    pub fn refresh(&mut self, client: &Client, discovery: &Discovery) -> Result<()> {
-        CachedKeys::new(client, discovery).map(move |keys| {
-            self.keys = keys.keys;
-            self.expiry = keys.expiry;
-            ()
-        })
+    /// Writes the keys and their expiry in the form `from_cache` reads.
+    pub fn save<T: Write>(&self, write: T) -> Result<()> {
+        serde_json::to_writer(write, self).map_err(|err| ErrorKind::JsonError(err).into())
     }
 
-    pub fn keys(&mut self, client: &Client, discovery: &Discovery) -> Result<&Vec<Key>> {
-        if self.is_expired() {
-            CachedKeys::new(client, discovery).map(move |keys| {
-                self.keys = keys.keys;
-                self.expiry = keys.expiry;
-                &self.keys
-            })
-        } else {
-            Ok(&self.keys)
-        }
+    /// The cached keys, even if they have expired.
+    pub fn current(&self) -> &[Key] {
+        &self.keys
     }
 
-    pub fn keys_opt(&self) -> Option<&Vec<Key>> {
-        if self.is_expired() {
-            None
-        } else {
-            Some(&self.keys)
-        }
+    /// Whether the cache will have expired `duration` from now.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        Utc::now() + duration >= self.expiry
     }
 
     pub fn is_expired(&self) -> bool {
         Utc::now() >= self.expiry
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // testdata holds a locally generated key pair, with the public half as a JWK and as the DER
+    // written by `openssl rsa -RSAPublicKey_out -outform DER`.
+    fn key() -> Key {
+        serde_json::from_str(include_str!("../../testdata/rsa_public.jwk.json")).unwrap()
+    }
+
+    #[test]
+    fn builds_pkcs1_public_key_from_modulus_and_exponent() {
+        assert_eq!(&include_bytes!("../../testdata/rsa_public.der")[..], &key().public_key_der().unwrap()[..]);
+    }
+
+    #[test]
+    fn rejects_other_key_types() {
+        let mut key = key();
+        key.kty = "EC".to_string();
+        assert!(key.public_key_der().is_err());
+    }
+
+    #[test]
+    fn cached_keys_round_trip_through_a_cache_file() {
+        let cached = CachedKeys {
+            keys: vec![key()],
+            expiry: Utc::now() + Duration::hours(1)
+        };
+        let mut file = Vec::new();
+        cached.save(&mut file).unwrap();
+
+        let loaded = CachedKeys::from_cache(&file[..]).unwrap();
+        assert!(!loaded.is_expired());
+        assert_eq!(cached.expiry, loaded.expiry);
+        assert_eq!("test", loaded.current()[0].kid);
+        assert_eq!(Some("sig".to_string()), loaded.current()[0].use_on);
+    }
+
+    #[test]
+    fn encodes_integers_as_positive() {
+        let mut out = Vec::new();
+        der_integer(&[0x00, 0x01, 0x00, 0x01], &mut out);
+        der_integer(&[0x80], &mut out);
+        assert_eq!(vec![0x02, 0x03, 0x01, 0x00, 0x01, 0x02, 0x02, 0x00, 0x80], out);
+    }
+}
@@ -2,5 +2,5 @@ mod discovery;
 mod keys;
 pub mod error;
 
-pub use self::discovery::{CachedDiscovery, Discovery};
-pub use self::keys::{Key, CachedKeys};
\ No newline at end of file
+pub use self::discovery::{CachedDiscovery, Discovery, discovery_url};
+pub use self::keys::{Key, CachedKeys, decode_url_safe};
\ No newline at end of file
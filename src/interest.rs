@@ -0,0 +1,305 @@
+//! Interest accrual for I.O.Stus.
+//!
+//! All amounts are integer pence. Interest is accrued per UTC calendar day on a 365 day year, in
+//! segments running from disbursement to each repayment and from the last repayment to the date of
+//! the statement. Within a segment, daily compounding is carried out at a precision of
+//! 1/`SCALE` pence, and the interest for the whole segment is then rounded half up to the nearest
+//! penny. Repayments pay off accrued interest before principal.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use config::{Config, Value};
+
+use errors::*;
+use money::Money;
+
+/// Sub-penny precision used while compounding within a segment.
+pub const SCALE: i64 = 10_000;
+
+const BASIS_POINTS: i64 = 10_000;
+const DAYS_PER_YEAR: i64 = 365;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    /// Interest accrues on the outstanding principal only.
+    Simple,
+    /// Interest accrues daily on the outstanding principal plus any unpaid interest.
+    DailyCompound
+}
+
+impl AsRef<str> for Method {
+    fn as_ref(&self) -> &str {
+        match *self {
+            Method::Simple => "simple",
+            Method::DailyCompound => "daily_compound"
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl FromStr for Method {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Method> {
+        match s {
+            "simple" => Ok(Method::Simple),
+            "daily_compound" => Ok(Method::DailyCompound),
+            _ => Err(Error::from(ErrorKind::InvalidRequestDataError("interest_method".to_string())))
+        }
+    }
+}
+
+/// The interest terms of a request, with the annual rate given in basis points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Terms {
+    pub method: Method,
+    pub annual_rate_bps: i32
+}
+
+impl Default for Terms {
+    fn default() -> Terms {
+        Terms {
+            method: Method::Simple,
+            annual_rate_bps: 0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Balance {
+    pub principal: Money,
+    pub interest: Money,
+    pub accrued_interest: Money,
+    pub total_owed: Money
+}
+
+impl Terms {
+    /// The default terms for new requests, from the optional `interest` table in the config.
+    pub fn from_config(config: &Config) -> Result<Terms> {
+        let table = match config.get_table("interest") {
+            Ok(table) => table,
+            Err(_) => return Ok(Terms::default())
+        };
+
+        let method = match table.get("method") {
+            Some(v) => v.clone().into_str()
+                .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?
+                .parse::<Method>()
+                .map_err(|_| Error::from(ErrorKind::InvalidConfigTypeError("interest.method".to_string(),
+                                                                            "simple or daily_compound".to_string())))?,
+            None => Method::Simple
+        };
+        let annual_rate_bps = match table.get("annual_rate_bps") {
+            Some(v) => non_negative_int(v, "interest.annual_rate_bps")?,
+            None => 0
+        };
+
+        Ok(Terms {
+            method,
+            annual_rate_bps
+        })
+    }
+
+    /// Interest in pence accrued over `days` on `principal` with `interest` already unpaid.
+    pub fn accrue(&self, principal: i64, interest: i64, days: i64) -> i64 {
+        if days <= 0 || self.annual_rate_bps == 0 {
+            return 0
+        }
+        let rate = i64::from(self.annual_rate_bps);
+
+        match self.method {
+            Method::Simple => div_round(principal * rate * days, BASIS_POINTS * DAYS_PER_YEAR),
+            Method::DailyCompound => {
+                let start = (principal + interest) * SCALE;
+                let end = (0..days).fold(start, |balance, _|
+                    balance + div_round(balance * rate, BASIS_POINTS * DAYS_PER_YEAR));
+                div_round(end - start, SCALE)
+            }
+        }
+    }
+
+    /// The balance of a loan of `principal` disbursed at `disbursed`, after the given repayments,
+    /// as of `as_of`. Repayments must be in the order they were paid.
+    pub fn balance(&self, principal: Money, disbursed: DateTime<Utc>, repayments: &[(DateTime<Utc>, Money)],
+                   as_of: DateTime<Utc>) -> Balance {
+        let mut principal = principal.minor_units();
+        let mut interest = 0;
+        let mut accrued = 0;
+        let mut from = disbursed;
+
+        for &(paid_at, amount) in repayments {
+            let added = self.accrue(principal, interest, days_between(from, paid_at));
+            interest += added;
+            accrued += added;
+
+            let amount = amount.minor_units();
+            let to_interest = amount.min(interest);
+            interest -= to_interest;
+            principal -= (amount - to_interest).min(principal);
+            if paid_at > from {
+                from = paid_at;
+            }
+        }
+        let added = self.accrue(principal, interest, days_between(from, as_of));
+        interest += added;
+        accrued += added;
+
+        Balance {
+            principal: Money::from_minor_units(principal),
+            interest: Money::from_minor_units(interest),
+            accrued_interest: Money::from_minor_units(accrued),
+            total_owed: Money::from_minor_units(principal + interest)
+        }
+    }
+}
+
+/// The number of UTC calendar days from `from` to `to`.
+pub fn days_between(from: DateTime<Utc>, to: DateTime<Utc>) -> i64 {
+    to.naive_utc().date().signed_duration_since(from.naive_utc().date()).num_days()
+}
+
+/// Divides non-negative `n` by positive `d`, rounding half up.
+fn div_round(n: i64, d: i64) -> i64 {
+    (n + d / 2) / d
+}
+
+/// Reads the config value `id`, which must be an integer from zero to `i32::MAX`.
+pub fn non_negative_int(value: &Value, id: &str) -> Result<i32> {
+    let int = value.clone().into_int().map_err(|err| Error::from(ErrorKind::ConfigError(err)))?;
+    if int < 0 || int > i64::from(i32::MAX) {
+        bail!(ErrorKind::InvalidConfigTypeError(id.to_string(), "whole number from 0 to 2147483647".to_string()))
+    }
+    Ok(int as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use config::{File, FileFormat};
+
+    fn day(n: i64) -> DateTime<Utc> {
+        Utc.ymd(2017, 1, 1).and_hms(12, 0, 0) + ::chrono::Duration::days(n)
+    }
+
+    fn pence(n: i64) -> Money {
+        Money::from_minor_units(n)
+    }
+
+    fn owed(principal: i64, interest: i64, accrued_interest: i64) -> Balance {
+        Balance {
+            principal: pence(principal),
+            interest: pence(interest),
+            accrued_interest: pence(accrued_interest),
+            total_owed: pence(principal + interest)
+        }
+    }
+
+    fn config(toml: &str) -> Config {
+        let mut config = Config::new();
+        config.merge(File::from_str(toml, FileFormat::Toml)).unwrap();
+        config
+    }
+
+    #[test]
+    fn terms_are_read_from_the_interest_table() {
+        assert_eq!(Terms::default(), Terms::from_config(&config("")).unwrap());
+        assert_eq!(Terms { method: Method::DailyCompound, annual_rate_bps: 500 },
+                   Terms::from_config(&config("[interest]\n\
+                                               method = \"daily_compound\"\n\
+                                               annual_rate_bps = 500\n")).unwrap());
+    }
+
+    #[test]
+    fn rates_must_fit_the_database() {
+        assert!(Terms::from_config(&config("[interest]\nannual_rate_bps = -1\n")).is_err());
+        assert!(Terms::from_config(&config("[interest]\nannual_rate_bps = 2147483648\n")).is_err());
+        assert!(Terms::from_config(&config("[interest]\nannual_rate_bps = 2147483647\n")).is_ok());
+    }
+
+    #[test]
+    fn simple_interest_for_a_year() {
+        let terms = Terms { method: Method::Simple, annual_rate_bps: 500 };
+        // £1000.00 at 5% for 365 days is exactly £50.00
+        assert_eq!(5000, terms.accrue(100_000, 0, 365));
+    }
+
+    #[test]
+    fn simple_interest_rounds_half_up() {
+        let terms = Terms { method: Method::Simple, annual_rate_bps: 500 };
+        // 100000 * 500 * 30 / 3650000 = 410.96p
+        assert_eq!(411, terms.accrue(100_000, 0, 30));
+
+        let terms = Terms { method: Method::Simple, annual_rate_bps: 1 };
+        // 1825000 * 1 * 1 / 3650000 = 0.5p exactly
+        assert_eq!(1, terms.accrue(1_825_000, 0, 1));
+        // 1824999 * 1 * 1 / 3650000 = 0.49999p
+        assert_eq!(0, terms.accrue(1_824_999, 0, 1));
+    }
+
+    #[test]
+    fn simple_interest_ignores_unpaid_interest() {
+        let terms = Terms { method: Method::Simple, annual_rate_bps: 500 };
+        assert_eq!(5000, terms.accrue(100_000, 2500, 365));
+    }
+
+    #[test]
+    fn daily_compound_interest() {
+        // 365% a year is 1% a day: £1000.00 -> £1010.00 -> £1020.10
+        let terms = Terms { method: Method::DailyCompound, annual_rate_bps: 36_500 };
+        assert_eq!(1000, terms.accrue(100_000, 0, 1));
+        assert_eq!(2010, terms.accrue(100_000, 0, 2));
+        // Unpaid interest is compounded too: £1000.00 + £10.00 -> £1020.10
+        assert_eq!(1010, terms.accrue(100_000, 1000, 1));
+    }
+
+    #[test]
+    fn daily_compound_keeps_sub_penny_precision_within_a_segment() {
+        // £10.00 at 3.65% is 0.1p a day, which would round to nothing if rounded daily
+        let terms = Terms { method: Method::DailyCompound, annual_rate_bps: 365 };
+        assert_eq!(0, terms.accrue(1000, 0, 4));
+        assert_eq!(1, terms.accrue(1000, 0, 5));
+        assert_eq!(3, terms.accrue(1000, 0, 30));
+    }
+
+    #[test]
+    fn zero_rate_accrues_nothing() {
+        let terms = Terms::default();
+        assert_eq!(0, terms.accrue(100_000, 0, 10_000));
+        assert_eq!(owed(100_000, 0, 0), terms.balance(pence(100_000), day(0), &[], day(365)));
+    }
+
+    #[test]
+    fn balance_with_partial_repayment() {
+        let terms = Terms { method: Method::Simple, annual_rate_bps: 500 };
+        // Days 0-73: 100000 * 500 * 73 / 3650000 = 1000p interest.
+        // The £500.00 repayment clears the £10.00 interest then £490.00 of principal, leaving £510.00.
+        // Days 73-146: 51000 * 500 * 73 / 3650000 = 510p interest.
+        assert_eq!(owed(51_000, 510, 1510),
+                   terms.balance(pence(100_000), day(0), &[(day(73), pence(50_000))], day(146)));
+    }
+
+    #[test]
+    fn balance_repaid_in_full() {
+        let terms = Terms { method: Method::DailyCompound, annual_rate_bps: 36_500 };
+        // £1000.00 at 1% a day, with the first day's £10.00 interest paid off before it compounds.
+        assert_eq!(owed(0, 0, 2000),
+                   terms.balance(pence(100_000), day(0), &[(day(1), pence(1000)), (day(2), pence(101_000))], day(30)));
+    }
+
+    #[test]
+    fn days_are_counted_by_calendar_date() {
+        let from = Utc.ymd(2017, 1, 1).and_hms(23, 59, 0);
+        let to = Utc.ymd(2017, 1, 2).and_hms(0, 1, 0);
+        assert_eq!(1, days_between(from, to));
+        assert_eq!(0, days_between(from, from));
+    }
+}
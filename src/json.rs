@@ -0,0 +1,17 @@
+use iron::prelude::*;
+use iron::status::Status;
+use iron::headers::ContentType;
+use serde::Serialize;
+use serde_json;
+
+use errors::*;
+
+pub fn response<T: Serialize>(status: Status, body: &T) -> Result<Response> {
+    serde_json::to_string(body)
+        .map_err(|err| Error::from(ErrorKind::JsonError(err)))
+        .map(|json| {
+            let mut response = Response::with((status, json));
+            response.headers.set(ContentType::json());
+            response
+        })
+}
@@ -0,0 +1,207 @@
+use std::fmt;
+use std::str::FromStr;
+
+use errors::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Pending,
+    Approved,
+    Rejected,
+    Disbursed,
+    Repaid,
+    WrittenOff,
+    Cancelled,
+    Expired
+}
+
+impl Status {
+    // The request_transitions table in sql/up.sql holds the same pairs for the database trigger,
+    // which the tests check.
+    pub fn can_transition(&self, to: Status) -> bool {
+        match (*self, to) {
+            (Status::Pending, Status::Approved) |
+            (Status::Pending, Status::Rejected) |
+            (Status::Pending, Status::Cancelled) |
+            (Status::Pending, Status::Expired) |
+            (Status::Approved, Status::Disbursed) |
+            (Status::Approved, Status::Expired) |
+            (Status::Disbursed, Status::Repaid) |
+            (Status::Disbursed, Status::WrittenOff) => true,
+            _ => false
+        }
+    }
+
+    pub fn transition(&self, to: Status) -> Result<Status> {
+        if self.can_transition(to) {
+            Ok(to)
+        } else {
+            Err(Error::from(ErrorKind::InvalidTransitionError(self.to_string(), to.to_string())))
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        match *self {
+            Status::Pending | Status::Approved | Status::Disbursed => true,
+            _ => false
+        }
+    }
+}
+
+impl AsRef<str> for Status {
+    fn as_ref(&self) -> &str {
+        match *self {
+            Status::Pending => "pending",
+            Status::Approved => "approved",
+            Status::Rejected => "rejected",
+            Status::Disbursed => "disbursed",
+            Status::Repaid => "repaid",
+            Status::WrittenOff => "written_off",
+            Status::Cancelled => "cancelled",
+            Status::Expired => "expired"
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl FromStr for Status {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Status> {
+        match s {
+            "pending" => Ok(Status::Pending),
+            "approved" => Ok(Status::Approved),
+            "rejected" => Ok(Status::Rejected),
+            "disbursed" => Ok(Status::Disbursed),
+            "repaid" => Ok(Status::Repaid),
+            "written_off" => Ok(Status::WrittenOff),
+            "cancelled" => Ok(Status::Cancelled),
+            "expired" => Ok(Status::Expired),
+            _ => Err(Error::from(ErrorKind::InvalidRequestDataError("status".to_string())))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Approve,
+    Reject,
+    Disburse,
+    Cancel
+}
+
+impl Action {
+    pub fn target(&self) -> Status {
+        match *self {
+            Action::Approve => Status::Approved,
+            Action::Reject => Status::Rejected,
+            Action::Disburse => Status::Disbursed,
+            Action::Cancel => Status::Cancelled
+        }
+    }
+
+    pub fn admin_only(&self) -> bool {
+        match *self {
+            Action::Approve | Action::Reject | Action::Disburse => true,
+            Action::Cancel => false
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Action> {
+        match s {
+            "approve" => Ok(Action::Approve),
+            "reject" => Ok(Action::Reject),
+            "disburse" => Ok(Action::Disburse),
+            "cancel" => Ok(Action::Cancel),
+            _ => Err(Error::from(ErrorKind::NotFoundError(format!("action {}", s))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const STATUSES: [Status; 8] = [Status::Pending, Status::Approved, Status::Rejected, Status::Disbursed,
+                                   Status::Repaid, Status::WrittenOff, Status::Cancelled, Status::Expired];
+
+    /// The pairs inserted into request_transitions, which the database trigger checks.
+    fn database_transitions() -> HashSet<(Status, Status)> {
+        let sql = include_str!("../sql/up.sql");
+        let insert = &sql[sql.find("INSERT INTO request_transitions").expect("request_transitions is filled")..];
+        let values = &insert[insert.find("VALUES").unwrap()..insert.find(';').unwrap()];
+        values.split('(')
+            .skip(1)
+            .map(|pair| {
+                let pair = &pair[..pair.find(')').unwrap()];
+                let statuses = pair.split(',')
+                    .map(|status| status.trim().trim_matches('\'').parse::<Status>().unwrap())
+                    .collect::<Vec<Status>>();
+                (statuses[0], statuses[1])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transitions_match_the_database() {
+        let database = database_transitions();
+        assert!(!database.is_empty());
+        for &from in &STATUSES {
+            for &to in &STATUSES {
+                assert_eq!(database.contains(&(from, to)), from.can_transition(to), "{} to {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn only_allowed_transitions_succeed() {
+        assert_eq!(Status::Approved, Status::Pending.transition(Status::Approved).unwrap());
+        assert_eq!(Status::Repaid, Status::Disbursed.transition(Status::Repaid).unwrap());
+        match *Status::Pending.transition(Status::Disbursed).unwrap_err().kind() {
+            ErrorKind::InvalidTransitionError(ref from, ref to) => {
+                assert_eq!("pending", from);
+                assert_eq!("disbursed", to);
+            },
+            ref kind => panic!("Expected an invalid transition, got {:?}", kind)
+        }
+        assert!(Status::Approved.transition(Status::Cancelled).is_err());
+        assert!(Status::Pending.transition(Status::Pending).is_err());
+    }
+
+    #[test]
+    fn closed_requests_can_not_move() {
+        for &from in STATUSES.iter().filter(|status| !status.is_open()) {
+            assert!(STATUSES.iter().all(|&to| !from.can_transition(to)), "{} can move", from);
+        }
+    }
+
+    #[test]
+    fn only_borrowers_cancel() {
+        assert!(Action::Approve.admin_only());
+        assert!(Action::Reject.admin_only());
+        assert!(Action::Disburse.admin_only());
+        assert!(!Action::Cancel.admin_only());
+        assert_eq!(Status::Cancelled, Action::Cancel.target());
+        assert!("approve".parse::<Action>().is_ok());
+        assert!("repay".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn statuses_round_trip_through_strings() {
+        for status in &STATUSES {
+            assert_eq!(*status, status.as_ref().parse::<Status>().unwrap());
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use iron::prelude::*;
+use iron::Handler;
+use iron::method::Method;
+use iron::status;
+use urlencoded::QueryResult;
+
+use errors::*;
+use providers::Database;
+use models::{self, LimitOverrides, NewLimitOverrides};
+use json;
+use params::{self, body, caller, single_value};
+use policy::{self, Limits};
+use money::{Amount, Money};
+use currency::Currency;
+
+pub struct LimitsHandler {
+    defaults: Limits,
+    home: Currency
+}
+
+#[derive(Debug, Serialize)]
+struct BorrowerLimits {
+    user_id: String,
+    defaults: Limits,
+    overrides: Option<LimitOverrides>,
+    limits: Limits
+}
+
+impl NewLimitOverrides {
+    fn from_query(map_res: QueryResult) -> Result<NewLimitOverrides> {
+        let mut map = map_res.map_err(|err| Error::from(ErrorKind::RequestDecodeError(err)))
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        Ok(NewLimitOverrides {
+            max_outstanding: match single_value(&mut map, "max_outstanding").chain_err(|| ErrorKind::BadRequestError)? {
+                Some(max) => Some(max.parse().chain_err(|| ErrorKind::BadRequestError)?),
+                None => None
+            },
+            max_request: match single_value(&mut map, "max_request").chain_err(|| ErrorKind::BadRequestError)? {
+                Some(max) => Some(max.parse().chain_err(|| ErrorKind::BadRequestError)?),
+                None => None
+            },
+            max_open_loans: match single_value(&mut map, "max_open_loans").chain_err(|| ErrorKind::BadRequestError)? {
+                Some(max) => Some(parse_count("max_open_loans", &max)?),
+                None => None
+            },
+            cooling_off_days: match single_value(&mut map, "cooling_off_days")
+                .chain_err(|| ErrorKind::BadRequestError)? {
+                Some(days) => Some(parse_count("cooling_off_days", &days)?),
+                None => None
+            }
+        })
+    }
+}
+
+impl LimitsHandler {
+    pub fn new(defaults: Limits, home: Currency) -> LimitsHandler {
+        LimitsHandler {
+            defaults,
+            home
+        }
+    }
+
+    fn show(&self, req: &mut Request, user_id: &str) -> Result<Response> {
+        let caller = caller(req)?;
+        if !caller.can_view_all() && caller.subject != user_id {
+            bail!(ErrorKind::ForbiddenError)
+        }
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let overrides = policy::overrides(&*con, user_id)?;
+        json::response(status::Ok, &BorrowerLimits {
+            user_id: user_id.to_string(),
+            defaults: self.defaults,
+            limits: overrides.as_ref().map_or(self.defaults, |overrides| self.defaults.overridden_by(overrides)),
+            overrides
+        })
+    }
+
+    fn set(&self, req: &mut Request, user_id: &str) -> Result<Response> {
+        let caller = caller(req)?;
+        if !caller.is_admin() {
+            bail!(ErrorKind::ForbiddenError)
+        }
+        let new = body(req, NewLimitOverrides::from_query)?;
+        let max_outstanding = in_currency(new.max_outstanding, self.home)?;
+        let max_request = in_currency(new.max_request, self.home)?;
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let overrides = con.query(&format!("INSERT INTO limit_overrides (user_id, max_outstanding, max_request, \
+                                            max_open_loans, cooling_off_days, updated_by) \
+                                            VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (user_id) DO UPDATE \
+                                            SET max_outstanding = EXCLUDED.max_outstanding, \
+                                            max_request = EXCLUDED.max_request, \
+                                            max_open_loans = EXCLUDED.max_open_loans, \
+                                            cooling_off_days = EXCLUDED.cooling_off_days, \
+                                            updated_by = EXCLUDED.updated_by, updated_at = now() \
+                                            RETURNING {};", models::LIMIT_OVERRIDE_COLUMNS),
+                                  &[&user_id, &max_outstanding, &max_request, &new.max_open_loans,
+                                    &new.cooling_off_days, &caller.subject])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?
+            .iter()
+            .next()
+            .map(|row| LimitOverrides::from_row(&row))
+            .ok_or_else(|| Error::from(ErrorKind::InternalServerError))?;
+
+        json::response(status::Ok, &BorrowerLimits {
+            user_id: user_id.to_string(),
+            defaults: self.defaults,
+            limits: self.defaults.overridden_by(&overrides),
+            overrides: Some(overrides)
+        })
+    }
+
+    fn clear(&self, req: &mut Request, user_id: &str) -> Result<Response> {
+        let caller = caller(req)?;
+        if !caller.is_admin() {
+            bail!(ErrorKind::ForbiddenError)
+        }
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        con.execute("DELETE FROM limit_overrides WHERE user_id = $1;", &[&user_id])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        Ok(Response::with(status::NoContent))
+    }
+}
+
+impl Handler for LimitsHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = params::path(req);
+
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Get, 1) => self.show(req, &path[0]),
+            (Method::Post, 1) => self.set(req, &path[0]),
+            (Method::Delete, 1) => self.clear(req, &path[0]),
+            (_, 1) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support GET, POST and DELETE!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+}
+
+fn in_currency(amount: Option<Amount>, home: Currency) -> Result<Option<Money>> {
+    match amount {
+        Some(amount) => amount.in_currency(home).map(Some),
+        None => Ok(None)
+    }
+}
+
+fn parse_count(name: &str, s: &str) -> Result<i32> {
+    s.parse::<i32>()
+        .ok()
+        .and_then(|count| if count >= 0 { Some(count) } else { None })
+        .ok_or_else(|| Error::from(ErrorKind::InvalidRequestDataError(name.to_string())))
+        .chain_err(|| ErrorKind::BadRequestError)
+}
@@ -1,30 +1,108 @@
-use std::io::Read;
+use std::sync::Arc;
 
 use iron::prelude::*;
 use iron::{status, Handler};
+use iron::method::Method;
+use urlencoded::QueryResult;
 
-use providers::Log;
 use errors::*;
+use providers::{self, Database, Device, IdentityProviders, Sessions, SessionTokens};
+use models::User;
+use registration::{self, Registration};
+use roles;
+use json;
+use params::{self, body, bearer_token, required_value};
 
+/// Swaps an ID token for a riostu session, and refresh tokens for fresh ones.
 pub struct LoginHandler {
+    providers: Arc<IdentityProviders>,
+    sessions: Arc<Sessions>,
+    registration: Registration,
+    admin: Option<String>
+}
 
+#[derive(Debug, Clone, Deserialize)]
+struct Refresh {
+    refresh_token: String
+}
+
+impl Refresh {
+    fn from_query(map_res: QueryResult) -> Result<Refresh> {
+        let mut map = map_res.map_err(|err| Error::from(ErrorKind::RequestDecodeError(err)))
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let refresh_token = required_value(&mut map, "refresh_token")
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        Ok(Refresh {
+            refresh_token
+        })
+    }
 }
 
 impl LoginHandler {
-    pub fn new() -> LoginHandler {
-        LoginHandler {}
+    pub fn new(providers: Arc<IdentityProviders>, sessions: Arc<Sessions>, registration: Registration,
+               admin: Option<String>) -> LoginHandler {
+        LoginHandler {
+            providers,
+            sessions,
+            registration,
+            admin
+        }
+    }
+
+    fn login(&self, req: &mut Request) -> IronResult<Response> {
+        let claims = bearer_token(req)
+            .and_then(|token| self.providers.verify(&token))
+            .map_err(providers::unauthorized)?;
+        let user = User {
+            name: claims.name.unwrap_or_else(|| claims.email.clone().unwrap_or_default()),
+            email: claims.email.unwrap_or_default(),
+            id: claims.sub
+        };
+
+        self.start_session(req, &user)
+            .and_then(|tokens| json::response(status::Ok, &tokens))
+            .map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+
+    fn start_session(&self, req: &Request, user: &User) -> Result<SessionTokens> {
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let trans = con.transaction()
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+
+        let admin = self.admin.as_ref().map(String::as_str);
+        let user = registration::provision(&trans, &self.registration, admin, user)?;
+        let tokens = roles::sign_in(&trans, admin, &user.id)
+            .and_then(|_| self.sessions.issue(&trans, &user, &Device::from_request(req)))?;
+
+        trans.commit()
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        Ok(tokens)
+    }
+
+    fn refresh(&self, req: &mut Request) -> Result<Response> {
+        let refresh = body(req, Refresh::from_query)?;
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let tokens = self.sessions.refresh(&*con, &refresh.refresh_token, &Device::from_request(req))?;
+        json::response(status::Ok, &tokens)
     }
 }
 
 impl Handler for LoginHandler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        if let Some(log) = req.extensions.get::<Log>() {
-            info!(log, "Request: {:?}", req);
-            let mut s = String::new();
-            req.body.read_to_string(&mut s);
-            info!(log, "Body: {}", s);
-        }
+        let path = params::path(req);
 
-        Ok(Response::with(status::ImATeapot))
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Post, 0) => return self.login(req),
+            (Method::Post, 1) if path[0] == "refresh" => self.refresh(req),
+            (_, 0) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support POST!"))),
+            (_, 1) if path[0] == "refresh" =>
+                return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support POST!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
     }
-}
\ No newline at end of file
+}
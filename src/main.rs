@@ -23,11 +23,13 @@ extern crate base64;
 extern crate serde_json;
 extern crate chrono;
 extern crate jsonwebtoken as jwt;
+extern crate rand;
+extern crate sha2;
 
 use std::fs;
 use std::io;
 use std::io::{Write, Read};
-use std::collections::HashSet;
+use std::sync::Arc;
 
 use iron::prelude::*;
 use mount::Mount;
@@ -45,6 +47,23 @@ mod providers;
 mod google;
 mod auth;
 mod models;
+mod json;
+mod lifecycle;
+mod interest;
+mod money;
+mod params;
+mod currency;
+mod exchange;
+mod dashboard;
+mod policy;
+mod limits;
+mod schedule;
+mod roles;
+mod registration;
+mod personal_tokens;
+mod tokens;
+mod users;
+mod sessions;
 
 use errors::*;
 
@@ -63,44 +82,71 @@ fn main() {
         }
     };
 
-    let mut paths = HashSet::new();
-    paths.insert("/auth".to_string());
-    paths.shrink_to_fit();
-
-    match start_server(&log, &config, paths) {
+    match start_server(&log, &config) {
         Ok(_) => info!(log, "Successfully started the server"),
         Err(err) => error!(log, "Failed to start server! {}", err)
     }
 }
 
-fn build_auth(config: &Config, paths: HashSet<String>) -> Result<providers::Auth> {
+fn build_providers(log: &Logger, config: &Config) -> Result<providers::IdentityProviders> {
     let client = NativeTlsClient::new().map_err(|err| Error::from(ErrorKind::ClientTlsError(err)))?;
     let client = Client::with_connector(HttpsConnector::new(client));
-    providers::Auth::new(client, paths)
+    providers::IdentityProviders::from_config(config, client, log)
 }
 
-fn start_server(log: &Logger, config: &Config, paths: HashSet<String>) -> Result<iron::Listening> {
+fn start_server(log: &Logger, config: &Config) -> Result<iron::Listening> {
     let ssl = build_ssl(config)?;
     debug!(log, "Initialised SSL");
-    let auth_provider = build_auth(config, paths)?;
-    debug!(log, "Initialised Authentication");
+    let admin = build_admin(config)?;
+    let identity_providers = Arc::new(build_providers(log, config)?);
+    providers::IdentityProviders::refresh_in_background(&identity_providers)?;
     let db_provider = providers::Database::new(config)?;
     debug!(log, "Initialised Database");
+    let sessions = Arc::new(providers::Sessions::from_config(config)?);
+    sessions.load_revoked(&*db_provider.connect()?)?;
+    let auth_provider = providers::Auth::new(providers::AccessPolicy::from_config(config)?, sessions.clone());
+    debug!(log, "Initialised Authentication");
+    let home = currency::Currency::from_config(config)?;
+    let lending_limits = policy::Limits::from_config(config, home)?;
+    let registration = registration::Registration::from_config(config)?;
+    let terms = interest::Terms::from_config(config)?;
 
     let mut mount = Mount::new();
     mount.mount("/", Static::new("web/"))
-        .mount("/request", request::RequestHandler{})
-        .mount("/login", login::LoginHandler::new());
+        .mount("/request", request::RequestHandler::new(terms, home, lending_limits))
+        .mount("/exchange-rates", exchange::ExchangeRateHandler::new(home))
+        .mount("/dashboard", dashboard::DashboardHandler::new(home))
+        .mount("/limits", limits::LimitsHandler::new(lending_limits, home))
+        .mount("/users", users::UsersHandler::new())
+        .mount("/tokens", tokens::TokensHandler::new())
+        .mount("/sessions", sessions::SessionsHandler::new(sessions.clone()))
+        .mount("/logout", sessions::LogoutHandler::new(sessions.clone()))
+        .mount("/login", login::LoginHandler::new(identity_providers, sessions, registration, admin));
     let mut chain = Chain::new(mount);
     chain.link_before(providers::Log::new(log.new(o!())))
         .link_before(providers::Monitoring {})
-//        .link_before(auth_provider) TODO: Uncomment, change auth to return UUID to caller
-        .link_before(db_provider);
+        .link_before(db_provider)
+        .link_before(auth_provider);
     chain.link_after(providers::Monitoring {})
         .link_after(providers::ErrorCapture {});
     build_iron(config, chain, ssl)
 }
 
+/// The user made the first admin, if the `admin` table names one.
+fn build_admin(config: &Config) -> Result<Option<String>> {
+    let admin_table = match config.get_table("admin") {
+        Ok(table) => table,
+        Err(_) => return Ok(None)
+    };
+
+    match admin_table.get("user_id") {
+        Some(v) => v.clone().into_str()
+            .map(|user_id| if user_id.is_empty() { None } else { Some(user_id) })
+            .map_err(|err| Error::from(ErrorKind::ConfigError(err))),
+        None => Ok(None)
+    }
+}
+
 fn build_ssl(config: &Config) -> Result<NativeTlsServer> {
     let ssl_table = config.get_table("ssl")
         .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?;
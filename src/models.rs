@@ -1,22 +1,339 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use postgres::rows::Row;
+
+use errors::*;
+use lifecycle::Status;
+use interest::{self, Terms, Balance};
+use money::{Amount, Money};
+use currency::{Currency, Rate};
+use schedule::Frequency;
+use personal_tokens::Scope;
+
+pub const REQUEST_COLUMNS: &str = "id, user_id, amount, currency, status, interest_method, \
+                                      interest_rate_bps, disbursed_at, created_at, updated_at";
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Request {
     pub id: i32,
     pub user_id: String,
-    pub amount: i32
+    pub amount: Money,
+    pub currency: Currency,
+    pub status: Status,
+    pub interest_method: interest::Method,
+    pub interest_rate_bps: i32,
+    pub disbursed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>
+}
+
+impl Request {
+    pub fn from_row(row: &Row) -> Result<Request> {
+        Ok(Request {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            amount: row.get("amount"),
+            currency: row.get("currency"),
+            status: parse_column(row, "status")?,
+            interest_method: parse_column(row, "interest_method")?,
+            interest_rate_bps: row.get("interest_rate_bps"),
+            disbursed_at: row.get("disbursed_at"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at")
+        })
+    }
+
+    pub fn terms(&self) -> Terms {
+        Terms {
+            method: self.interest_method,
+            annual_rate_bps: self.interest_rate_bps
+        }
+    }
+
+    pub fn balance(&self, repayments: &[Repayment]) -> Balance {
+        match self.disbursed_at {
+            Some(disbursed) => {
+                // Interest stops accruing once a request leaves the disbursed state.
+                let as_of = if self.status == Status::Disbursed {
+                    Utc::now()
+                } else {
+                    self.updated_at
+                };
+                let paid = repayments.iter()
+                    .map(|repayment| (repayment.paid_at, repayment.amount))
+                    .collect::<Vec<_>>();
+                self.terms().balance(self.amount, disbursed, &paid, as_of)
+            },
+            None => Balance {
+                principal: self.amount,
+                interest: Money::zero(),
+                accrued_interest: Money::zero(),
+                total_owed: self.amount
+            }
+        }
+    }
+}
+
+/// Parses the text `column` of `row`. The database constrains it, so a value that doesn't parse
+/// means the schema and the code have drifted apart.
+pub fn parse_column<T>(row: &Row, column: &str) -> Result<T> where T: FromStr<Err=Error> {
+    parse_value(column, row.get(column))
+}
+
+/// Parses each value of the text array `column`, constrained like the values of `parse_column`.
+pub fn parse_values<T>(column: &str, values: Vec<String>) -> Result<Vec<T>> where T: FromStr<Err=Error> {
+    values.into_iter()
+        .map(|value| parse_value(column, value))
+        .collect()
+}
+
+fn parse_value<T>(column: &str, value: String) -> Result<T> where T: FromStr<Err=Error> {
+    value.parse()
+        .chain_err(|| ErrorKind::InvalidColumnError(column.to_string(), value.clone()))
+        .chain_err(|| ErrorKind::InternalServerError)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestEvent {
+    pub id: i32,
+    pub request_id: i32,
+    pub kind: String,
+    pub actor: String,
+    pub amount: Option<Money>,
+    pub created_at: DateTime<Utc>
+}
+
+impl RequestEvent {
+    pub fn from_row(row: &Row) -> RequestEvent {
+        RequestEvent {
+            id: row.get("id"),
+            request_id: row.get("request_id"),
+            kind: row.get("kind"),
+            actor: row.get("actor"),
+            amount: row.get("amount"),
+            created_at: row.get("created_at")
+        }
+    }
+}
+
+pub const REPAYMENT_COLUMNS: &str = "id, request_id, amount, recorded_by, paid_at";
+
+/// A repayment, which is always in the currency of its request.
+#[derive(Debug, Clone, Serialize)]
+pub struct Repayment {
+    pub id: i32,
+    pub request_id: i32,
+    pub amount: Money,
+    pub recorded_by: String,
+    pub paid_at: DateTime<Utc>
+}
+
+impl Repayment {
+    pub fn from_row(row: &Row) -> Repayment {
+        Repayment {
+            id: row.get("id"),
+            request_id: row.get("request_id"),
+            amount: row.get("amount"),
+            recorded_by: row.get("recorded_by"),
+            paid_at: row.get("paid_at")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewRepayment {
+    pub amount: Amount,
+    pub currency: Option<Currency>
 }
 
+#[derive(Debug, Clone, Deserialize)]
 pub struct NewRequest {
-    pub user_id: String,
-    pub amount: i32
+    pub amount: Amount,
+    pub currency: Option<Currency>,
+    pub due_date: Option<NaiveDate>,
+    pub instalments: Option<i32>,
+    pub frequency: Option<Frequency>,
+    pub first_due: Option<NaiveDate>
+}
+
+pub const EXCHANGE_RATE_COLUMNS: &str = "currency, effective_date, rate_micros, updated_by, updated_at";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExchangeRate {
+    pub currency: Currency,
+    pub effective_date: NaiveDate,
+    pub rate: Rate,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>
+}
+
+impl ExchangeRate {
+    pub fn from_row(row: &Row) -> ExchangeRate {
+        ExchangeRate {
+            currency: row.get("currency"),
+            effective_date: row.get("effective_date"),
+            rate: Rate::from_micros(row.get("rate_micros")),
+            updated_by: row.get("updated_by"),
+            updated_at: row.get("updated_at")
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
+pub struct NewExchangeRate {
+    pub currency: Currency,
+    pub effective_date: NaiveDate,
+    pub rate: Rate
+}
+
+pub const USER_COLUMNS: &str = "id, name, email";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
     pub name: String,
     pub email: String
 }
 
+impl User {
+    pub fn from_row(row: &Row) -> User {
+        User {
+            id: row.get("id"),
+            name: row.get("name"),
+            email: row.get("email")
+        }
+    }
+}
+
+pub const TOKEN_COLUMNS: &str = "id, user_id, token_hash, expires_at, created_at, refreshed_at, last_used_at, ip, \
+                                  user_agent, revoked_at";
+
+/// A session, which lasts as long as its refresh token. It was last used at about `last_used_at`,
+/// from `ip` and `user_agent`.
+#[derive(Debug, Clone, Serialize)]
 pub struct Token {
+    pub id: i32,
+    pub user_id: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub refreshed_at: Option<DateTime<Utc>>,
+    pub last_used_at: DateTime<Utc>,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub revoked_at: Option<DateTime<Utc>>
+}
+
+impl Token {
+    pub fn from_row(row: &Row) -> Token {
+        Token {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            token_hash: row.get("token_hash"),
+            expires_at: row.get("expires_at"),
+            created_at: row.get("created_at"),
+            refreshed_at: row.get("refreshed_at"),
+            last_used_at: row.get("last_used_at"),
+            ip: row.get("ip"),
+            user_agent: row.get("user_agent"),
+            revoked_at: row.get("revoked_at")
+        }
+    }
+}
+
+pub const LIMIT_OVERRIDE_COLUMNS: &str = "user_id, max_outstanding, max_request, max_open_loans, cooling_off_days, \
+                                          updated_by, updated_at";
+
+/// Per-borrower replacements for the default lending limits. `None` falls back to the default.
+#[derive(Debug, Clone, Serialize)]
+pub struct LimitOverrides {
+    pub user_id: String,
+    pub max_outstanding: Option<Money>,
+    pub max_request: Option<Money>,
+    pub max_open_loans: Option<i32>,
+    pub cooling_off_days: Option<i32>,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>
+}
+
+impl LimitOverrides {
+    pub fn from_row(row: &Row) -> LimitOverrides {
+        LimitOverrides {
+            user_id: row.get("user_id"),
+            max_outstanding: row.get("max_outstanding"),
+            max_request: row.get("max_request"),
+            max_open_loans: row.get("max_open_loans"),
+            cooling_off_days: row.get("cooling_off_days"),
+            updated_by: row.get("updated_by"),
+            updated_at: row.get("updated_at")
+        }
+    }
+}
+
+/// Overrides as given by an admin, with amounts in the home currency.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewLimitOverrides {
+    pub max_outstanding: Option<Amount>,
+    pub max_request: Option<Amount>,
+    pub max_open_loans: Option<i32>,
+    pub cooling_off_days: Option<i32>
+}
+
+pub const INSTALMENT_COLUMNS: &str = "id, request_id, number, due_date, amount";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Instalment {
+    pub id: i32,
+    pub request_id: i32,
+    pub number: i32,
+    pub due_date: NaiveDate,
+    pub amount: Money
+}
+
+impl Instalment {
+    pub fn from_row(row: &Row) -> Instalment {
+        Instalment {
+            id: row.get("id"),
+            request_id: row.get("request_id"),
+            number: row.get("number"),
+            due_date: row.get("due_date"),
+            amount: row.get("amount")
+        }
+    }
+}
+
+pub const PERSONAL_TOKEN_COLUMNS: &str = "id, user_id, name, scopes, expires_on, created_at, last_used_at";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PersonalToken {
+    pub id: i32,
     pub user_id: String,
-    pub token: String
-}
\ No newline at end of file
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_on: Option<NaiveDate>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>
+}
+
+impl PersonalToken {
+    pub fn from_row(row: &Row) -> Result<PersonalToken> {
+        Ok(PersonalToken {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            name: row.get("name"),
+            scopes: parse_values("scopes", row.get("scopes"))?,
+            expires_on: row.get("expires_on"),
+            created_at: row.get("created_at"),
+            last_used_at: row.get("last_used_at")
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewPersonalToken {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_on: Option<NaiveDate>
+}
@@ -0,0 +1,331 @@
+//! An exact amount of money, held as a whole number of minor units (pence).
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+use std::error::Error as StdError;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+use postgres::types::{ToSql, FromSql, Type, IsNull};
+
+use errors::*;
+use currency::Currency;
+
+/// The number of decimal places in the minor unit.
+pub const SCALE: u32 = 2;
+
+const MINOR_PER_MAJOR: i64 = 100;
+const SYMBOLS: &[char] = &['£', '$', '€'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money(i64);
+
+impl Money {
+    /// The largest amount accepted from a user, £10,000,000.00.
+    pub const MAX: Money = Money(1_000_000_000);
+
+    pub fn from_minor_units(units: i64) -> Money {
+        Money(units)
+    }
+
+    pub fn minor_units(&self) -> i64 {
+        self.0
+    }
+
+    pub fn zero() -> Money {
+        Money(0)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn min(self, other: Money) -> Money {
+        if self <= other { self } else { other }
+    }
+}
+
+impl FromStr for Money {
+    type Err = Error;
+
+    /// Parses a positive amount such as `12`, `12.3`, `1,234.56` exactly, rejecting anything with
+    /// more than two decimal places, a sign, an exponent or a value above `Money::MAX`. A currency
+    /// symbol is refused too, as there is no currency to check it against, so amounts which may
+    /// have one should be parsed as an `Amount`.
+    fn from_str(s: &str) -> Result<Money> {
+        parse_exact(s.trim())
+    }
+}
+
+/// An amount given by a user, which may start with the symbol of its currency. The currency isn't
+/// always known while parsing, so the symbol is kept to be checked against it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amount {
+    money: Money,
+    symbol: Option<char>
+}
+
+impl Amount {
+    /// The amount in `currency`, failing if it was given with the symbol of another currency.
+    pub fn in_currency(&self, currency: Currency) -> Result<Money> {
+        match self.symbol {
+            Some(symbol) if currency.symbol() != Some(symbol) =>
+                bail!(ErrorKind::CurrencyMismatchError(symbol.to_string(), currency.to_string())),
+            _ => Ok(self.money)
+        }
+    }
+}
+
+impl FromStr for Amount {
+    type Err = Error;
+
+    /// Parses a `Money`, after at most one leading currency symbol.
+    fn from_str(s: &str) -> Result<Amount> {
+        let s = s.trim();
+        let mut chars = s.chars();
+        let (symbol, s) = match chars.next() {
+            Some(c) if SYMBOLS.contains(&c) => (Some(c), chars.as_str().trim_start()),
+            _ => (None, s)
+        };
+        parse_exact(s).map(|money| Amount {
+            money,
+            symbol
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Amount, D::Error> where D: Deserializer<'de> {
+        String::deserialize(deserializer)
+            .and_then(|s| s.parse().map_err(|err: Error| de::Error::custom(err)))
+    }
+}
+
+/// Parses an amount with no symbol exactly.
+fn parse_exact(s: &str) -> Result<Money> {
+    if s.starts_with('-') {
+        bail!(ErrorKind::AmountNotPositiveError)
+    }
+
+    let (whole, fraction) = match s.find('.') {
+        Some(point) => (&s[..point], &s[point + 1..]),
+        None => (s, "")
+    };
+    if s.contains('.') && (fraction.is_empty() || fraction.len() > SCALE as usize) {
+        bail!(ErrorKind::AmountParseError)
+    }
+    if whole.is_empty() || !is_grouped(whole) || !fraction.chars().all(|c| c.is_digit(10)) {
+        bail!(ErrorKind::AmountParseError)
+    }
+
+    let mut major = 0i64;
+    for c in whole.chars().filter(|&c| c != ',') {
+        major = major.checked_mul(10)
+            .and_then(|major| major.checked_add(digit(c)))
+            .ok_or_else(|| Error::from(ErrorKind::AmountTooLargeError(Money::MAX.to_string())))?;
+    }
+    let whole = major.checked_mul(MINOR_PER_MAJOR)
+        .ok_or_else(|| Error::from(ErrorKind::AmountTooLargeError(Money::MAX.to_string())))?;
+    let fraction = fraction.chars()
+        .chain(::std::iter::repeat('0'))
+        .take(SCALE as usize)
+        .fold(0i64, |acc, c| acc * 10 + digit(c));
+
+    let money = Money(whole + fraction);
+    if money.is_zero() {
+        bail!(ErrorKind::AmountNotPositiveError)
+    }
+    if money > Money::MAX {
+        bail!(ErrorKind::AmountTooLargeError(Money::MAX.to_string()))
+    }
+    Ok(money)
+}
+
+// Digits, optionally with a comma between every group of three.
+fn is_grouped(whole: &str) -> bool {
+    if !whole.contains(',') {
+        return whole.chars().all(|c| c.is_digit(10))
+    }
+    whole.split(',').enumerate().all(|(i, group)| {
+        let size_ok = if i == 0 { group.len() >= 1 && group.len() <= 3 } else { group.len() == 3 };
+        size_ok && group.chars().all(|c| c.is_digit(10))
+    })
+}
+
+fn digit(c: char) -> i64 {
+    i64::from(c.to_digit(10).unwrap_or(0))
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.abs();
+        write!(f, "{}{}.{:02}", sign, units / MINOR_PER_MAJOR, units % MINOR_PER_MAJOR)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item=Money>>(iter: I) -> Money {
+        iter.fold(Money::zero(), Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item=&'a Money>>(iter: I) -> Money {
+        iter.fold(Money::zero(), |acc, money| acc + *money)
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> where S: Serializer {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Money, D::Error> where D: Deserializer<'de> {
+        struct MoneyVisitor;
+
+        impl<'de> Visitor<'de> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an amount of money as a string, such as \"12.34\"")
+            }
+
+            fn visit_str<E>(self, s: &str) -> ::std::result::Result<Money, E> where E: de::Error {
+                s.parse().map_err(|err: Error| E::custom(err))
+            }
+        }
+
+        deserializer.deserialize_str(MoneyVisitor)
+    }
+}
+
+impl ToSql for Money {
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> ::std::result::Result<IsNull, Box<StdError + Sync + Send>> {
+        self.0.to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <i64 as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(&self, ty: &Type, out: &mut Vec<u8>) -> ::std::result::Result<IsNull, Box<StdError + Sync + Send>> {
+        self.0.to_sql_checked(ty, out)
+    }
+}
+
+impl FromSql for Money {
+    fn from_sql(ty: &Type, raw: &[u8]) -> ::std::result::Result<Money, Box<StdError + Sync + Send>> {
+        i64::from_sql(ty, raw).map(Money)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <i64 as FromSql>::accepts(ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Option<i64> {
+        s.parse::<Money>().ok().map(|money| money.minor_units())
+    }
+
+    fn amount(s: &str) -> Option<i64> {
+        s.parse::<Amount>().ok().map(|amount| amount.money.minor_units())
+    }
+
+    #[test]
+    fn parses_exactly() {
+        assert_eq!(Some(29), parse("0.29"));
+        assert_eq!(Some(1234), parse("12.34"));
+        assert_eq!(Some(1230), parse("12.3"));
+        assert_eq!(Some(1200), parse("12"));
+        assert_eq!(Some(1), parse("0.01"));
+    }
+
+    #[test]
+    fn accepts_symbols_and_separators() {
+        assert_eq!(Some(123_456), amount("£1,234.56"));
+        assert_eq!(Some(100_000_000), amount("$1,000,000"));
+        assert_eq!(Some(500), amount(" € 5 "));
+        assert_eq!(Some(123_456), parse("1,234.56"));
+        assert_eq!(None, parse("1,23.00"));
+        assert_eq!(None, parse("1234,567"));
+        assert_eq!(None, parse(",123"));
+    }
+
+    #[test]
+    fn accepts_at_most_one_symbol() {
+        assert_eq!(None, amount("££5"));
+        assert_eq!(None, amount("£$5"));
+        assert_eq!(None, amount("£ €5"));
+        assert_eq!(None, amount("5£"));
+    }
+
+    #[test]
+    fn money_has_no_symbol() {
+        assert_eq!(None, parse("£5"));
+        assert_eq!(None, parse("$1000"));
+        assert_eq!(Some(500), parse(" 5 "));
+    }
+
+    #[test]
+    fn symbols_must_be_of_the_currency() {
+        let pounds = Currency::default();
+        let dollars = "USD".parse::<Currency>().unwrap();
+        let yen = "JPY".parse::<Currency>().unwrap();
+        let amount = "£5".parse::<Amount>().unwrap();
+        assert_eq!(Money::from_minor_units(500), amount.in_currency(pounds).unwrap());
+        assert!(amount.in_currency(dollars).is_err());
+        assert!(amount.in_currency(yen).is_err());
+        assert_eq!(Money::from_minor_units(500), "5".parse::<Amount>().unwrap().in_currency(yen).unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        assert_eq!(None, parse(""));
+        assert_eq!(None, parse("£"));
+        assert_eq!(None, parse("-5"));
+        assert_eq!(None, parse("+5"));
+        assert_eq!(None, parse("0"));
+        assert_eq!(None, parse("0.00"));
+        assert_eq!(None, parse("1e3"));
+        assert_eq!(None, parse("NaN"));
+        assert_eq!(None, parse("inf"));
+        assert_eq!(None, parse("1.234"));
+        assert_eq!(None, parse("1."));
+        assert_eq!(None, parse(".5"));
+        assert_eq!(None, parse("10000000.01"));
+        assert_eq!(None, parse("99999999999999999999999"));
+        assert_eq!(Some(1_000_000_000), parse("10,000,000.00"));
+    }
+
+    #[test]
+    fn displays_with_two_decimal_places() {
+        assert_eq!("0.29", Money::from_minor_units(29).to_string());
+        assert_eq!("1234.50", Money::from_minor_units(123_450).to_string());
+        assert_eq!("-0.05", Money::from_minor_units(-5).to_string());
+    }
+}
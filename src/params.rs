@@ -0,0 +1,82 @@
+use iron::prelude::*;
+use iron::headers::{Authorization, Bearer, ContentType};
+use iron::mime::{Mime, TopLevel, SubLevel};
+use urlencoded::{QueryResult, QueryMap, UrlEncodedBody, UrlEncodedQuery, UrlDecodingError};
+use bodyparser::Struct;
+use serde::Deserialize;
+use chrono::NaiveDate;
+
+use errors::*;
+use providers::AuthenticatedUser;
+
+pub fn path(req: &Request) -> Vec<String> {
+    req.url.path().into_iter()
+        .filter(|segment| !segment.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+pub fn body<T>(req: &mut Request, from_query: fn(QueryResult) -> Result<T>) -> Result<T>
+    where T: 'static + Clone + for<'de> Deserialize<'de> {
+
+    let mime = req.headers.get::<ContentType>().map(|content| content.0.clone());
+    match mime {
+        Some(Mime(TopLevel::Application, SubLevel::Json, _)) => req.get::<Struct<T>>()
+            .map_err(|err| Error::from(ErrorKind::RequestBody2Error(err)))
+            .and_then(|body| body.ok_or_else(|| Error::from(ErrorKind::MissingRequestError)))
+            .chain_err(|| ErrorKind::BadRequestError),
+        Some(Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, _)) | None =>
+            from_query(req.get::<UrlEncodedBody>()),
+        Some(other) => Err(Error::from(ErrorKind::UnsupportedMediaTypeError(format!("{}", other))))
+    }
+}
+
+pub fn query_map(req: &mut Request) -> Result<QueryMap> {
+    match req.get::<UrlEncodedQuery>() {
+        Ok(map) => Ok(map),
+        Err(UrlDecodingError::EmptyQuery) => Ok(QueryMap::new()),
+        Err(err) => Err(Error::from(ErrorKind::RequestDecodeError(err)))
+            .chain_err(|| ErrorKind::BadRequestError)
+    }
+}
+
+/// The token from an `Authorization: Bearer` header.
+pub fn bearer_token(req: &Request) -> Result<String> {
+    match req.headers.get::<Authorization<Bearer>>() {
+        Some(auth) => Ok(auth.token.clone()),
+        None if req.headers.get_raw("Authorization").is_some() => bail!(ErrorKind::MalformedCredentialsError),
+        None => bail!(ErrorKind::MissingCredentialsError)
+    }
+}
+
+/// The user making the request, as verified by the Auth middleware.
+pub fn caller(req: &Request) -> Result<AuthenticatedUser> {
+    AuthenticatedUser::from_request(req)
+}
+
+pub fn single_value(map: &mut QueryMap, key: &str) -> Result<Option<String>> {
+    match map.remove(key) {
+        Some(mut values) => match values.len() {
+            1 => Ok(Some(values.remove(0))),
+            _ => Err(Error::from(ErrorKind::IncorrectCountRequestDataError(key.to_string(), 1)))
+        },
+        None => Ok(None)
+    }
+}
+
+/// Parses the id in a path, where one that isn't a number can't name any `what`.
+pub fn id(what: &str, s: &str) -> Result<i32> {
+    s.parse::<i32>()
+        .map_err(|_| Error::from(ErrorKind::NotFoundError(format!("{} {}", what, s))))
+}
+
+/// Parses a `YYYY-MM-DD` date from the `name` data.
+pub fn date(name: &str, s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| Error::from(ErrorKind::InvalidRequestDataError(name.to_string())))
+}
+
+pub fn required_value(map: &mut QueryMap, key: &str) -> Result<String> {
+    single_value(map, key)
+        .and_then(|value| value.ok_or_else(|| Error::from(ErrorKind::MissingRequestDataError(key.to_string()))))
+}
@@ -0,0 +1,153 @@
+//! Personal access tokens, for scripts that can't sign in with a browser.
+//!
+//! A personal token acts for the user who created it, but only on the routes its scopes cover.
+//! The token is shown once when it is created, and only its SHA-256 hash is stored.
+
+use std::fmt;
+use std::str::FromStr;
+
+use iron::method::Method;
+use postgres::GenericConnection;
+use chrono::NaiveDate;
+
+use errors::*;
+use models::{self, PersonalToken, NewPersonalToken};
+use providers::{self, AuthenticatedUser};
+use roles;
+
+/// Personal tokens start with this, so they can't be mistaken for a session token.
+pub const PREFIX: &str = "rst_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "requests:read")]
+    RequestsRead,
+    #[serde(rename = "requests:write")]
+    RequestsWrite
+}
+
+impl AsRef<str> for Scope {
+    fn as_ref(&self) -> &str {
+        match *self {
+            Scope::RequestsRead => "requests:read",
+            Scope::RequestsWrite => "requests:write"
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Scope> {
+        match s {
+            "requests:read" => Ok(Scope::RequestsRead),
+            "requests:write" => Ok(Scope::RequestsWrite),
+            _ => Err(Error::from(ErrorKind::InvalidRequestDataError("scopes".to_string())))
+        }
+    }
+}
+
+/// The scope a personal token needs to be used for `method` on `path`, or `None` if personal
+/// tokens can't be used there at all.
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let first = path.split('/').find(|segment| !segment.is_empty());
+    match (method, first) {
+        (&Method::Get, Some("request")) | (&Method::Head, Some("request")) |
+        (&Method::Get, Some("dashboard")) | (&Method::Head, Some("dashboard")) => Some(Scope::RequestsRead),
+        (&Method::Post, Some("request")) => Some(Scope::RequestsWrite),
+        _ => None
+    }
+}
+
+/// Creates a personal token for `user_id`, returning it along with the only copy of the token.
+pub fn create<C: GenericConnection>(con: &C, user_id: &str, new: &NewPersonalToken)
+    -> Result<(PersonalToken, String)> {
+
+    let token = format!("{}{}", PREFIX, providers::random_token()?);
+    let scopes = new.scopes.iter().map(|scope| scope.to_string()).collect::<Vec<String>>();
+    con.query(&format!("INSERT INTO personal_tokens (user_id, name, token_hash, scopes, expires_on) \
+                        VALUES ($1, $2, $3, $4, $5) RETURNING {};", models::PERSONAL_TOKEN_COLUMNS),
+              &[&user_id, &new.name, &providers::hash(&token), &scopes, &new.expires_on])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?
+        .iter()
+        .next()
+        .ok_or_else(|| Error::from(ErrorKind::InternalServerError))
+        .and_then(|row| PersonalToken::from_row(&row))
+        .map(|personal_token| (personal_token, token))
+}
+
+pub fn list<C: GenericConnection>(con: &C, user_id: &str) -> Result<Vec<PersonalToken>> {
+    con.query(&format!("SELECT {} FROM personal_tokens WHERE user_id = $1 AND revoked_at IS NULL \
+                        ORDER BY created_at, id;", models::PERSONAL_TOKEN_COLUMNS), &[&user_id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+        .and_then(|rows| rows.iter()
+            .map(|row| PersonalToken::from_row(&row))
+            .collect())
+}
+
+/// Revokes token `id` of `user_id`, returning false if they have no such token.
+pub fn revoke<C: GenericConnection>(con: &C, user_id: &str, id: i32) -> Result<bool> {
+    con.execute("UPDATE personal_tokens SET revoked_at = now() \
+                 WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL;", &[&id, &user_id])
+        .map(|updated| updated > 0)
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+}
+
+/// The user a personal token acts for, limited to its scopes.
+pub fn authenticate<C: GenericConnection>(con: &C, token: &str, today: NaiveDate) -> Result<AuthenticatedUser> {
+    let rows = con.query("UPDATE personal_tokens SET last_used_at = now() FROM users \
+                          WHERE personal_tokens.token_hash = $1 AND personal_tokens.revoked_at IS NULL \
+                          AND (personal_tokens.expires_on IS NULL OR personal_tokens.expires_on > $2) \
+                          AND users.id = personal_tokens.user_id \
+                          RETURNING users.id, users.name, users.email, personal_tokens.scopes;",
+                         &[&providers::hash(token), &today])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?;
+    let row = rows.iter()
+        .next()
+        .ok_or_else(|| Error::from(ErrorKind::InvalidPersonalTokenError))?;
+
+    let subject: String = row.get("id");
+    let scopes = models::parse_values("scopes", row.get("scopes"))?;
+    Ok(AuthenticatedUser {
+        roles: roles::roles(con, &subject)?,
+        email: Some(row.get("email")),
+        name: Some(row.get("name")),
+        scopes: Some(scopes),
+        session: None,
+        subject
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_and_writing_requests_need_their_own_scopes() {
+        assert_eq!(Some(Scope::RequestsRead), required_scope(&Method::Get, "/request/12/schedule"));
+        assert_eq!(Some(Scope::RequestsRead), required_scope(&Method::Get, "/dashboard"));
+        assert_eq!(Some(Scope::RequestsWrite), required_scope(&Method::Post, "/request"));
+        assert_eq!(Some(Scope::RequestsWrite), required_scope(&Method::Post, "/request/12/repayments"));
+        assert_eq!(None, required_scope(&Method::Post, "/dashboard"));
+        assert_eq!(None, required_scope(&Method::Post, "/tokens"));
+        assert_eq!(None, required_scope(&Method::Get, "/requests"));
+    }
+
+    #[test]
+    fn scopes_round_trip_through_strings() {
+        for scope in &[Scope::RequestsRead, Scope::RequestsWrite] {
+            assert_eq!(*scope, scope.as_ref().parse::<Scope>().unwrap());
+        }
+        assert!("requests:delete".parse::<Scope>().is_err());
+    }
+}
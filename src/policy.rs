@@ -0,0 +1,257 @@
+//! The lending policy every new request is checked against before it is created.
+//!
+//! Limits are in the home currency. The defaults come from the optional `policy` table in the
+//! config, and any of them can be replaced for a single borrower by a row in `limit_overrides`.
+
+use chrono::{DateTime, Utc};
+use config::{Config, Value};
+use postgres::GenericConnection;
+
+use errors::*;
+use models::{self, LimitOverrides};
+use money::{Amount, Money};
+use currency::{Currency, Rates};
+use interest::{days_between, non_negative_int};
+use request::repayment;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Limits {
+    /// The most a borrower may owe across all of their open requests, including the new one.
+    pub max_outstanding: Option<Money>,
+    /// The most that may be asked for in a single request.
+    pub max_request: Option<Money>,
+    /// The most requests a borrower may have open at once, including the new one.
+    pub max_open_loans: Option<i32>,
+    /// Days after a late repayment before the borrower may ask again.
+    pub cooling_off_days: i32
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_outstanding: None,
+            max_request: None,
+            max_open_loans: None,
+            cooling_off_days: 0
+        }
+    }
+}
+
+impl Limits {
+    /// The limits in the optional `policy` table of the config, whose amounts are in `home`.
+    pub fn from_config(config: &Config, home: Currency) -> Result<Limits> {
+        let table = match config.get_table("policy") {
+            Ok(table) => table,
+            Err(_) => return Ok(Limits::default())
+        };
+
+        Ok(Limits {
+            max_outstanding: money_value(table.get("max_outstanding"), "policy.max_outstanding", home)?,
+            max_request: money_value(table.get("max_request"), "policy.max_request", home)?,
+            max_open_loans: match table.get("max_open_loans") {
+                Some(v) => Some(non_negative_int(v, "policy.max_open_loans")?),
+                None => None
+            },
+            cooling_off_days: match table.get("cooling_off_days") {
+                Some(v) => non_negative_int(v, "policy.cooling_off_days")?,
+                None => 0
+            }
+        })
+    }
+
+    pub fn overridden_by(&self, overrides: &LimitOverrides) -> Limits {
+        Limits {
+            max_outstanding: overrides.max_outstanding.or(self.max_outstanding),
+            max_request: overrides.max_request.or(self.max_request),
+            max_open_loans: overrides.max_open_loans.or(self.max_open_loans),
+            cooling_off_days: overrides.cooling_off_days.unwrap_or(self.cooling_off_days)
+        }
+    }
+}
+
+fn money_value(value: Option<&Value>, id: &str, home: Currency) -> Result<Option<Money>> {
+    match value {
+        Some(v) => v.clone().into_str()
+            .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?
+            .parse::<Amount>()
+            .and_then(|amount| amount.in_currency(home))
+            .map(Some)
+            .map_err(|_| Error::from(ErrorKind::InvalidConfigTypeError(id.to_string(),
+                                                                       format!("amount in {}", home)))),
+        None => Ok(None)
+    }
+}
+
+pub fn overrides<C: GenericConnection>(con: &C, user_id: &str) -> Result<Option<LimitOverrides>> {
+    con.query(&format!("SELECT {} FROM limit_overrides WHERE user_id = $1;", models::LIMIT_OVERRIDE_COLUMNS),
+              &[&user_id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+        .map(|rows| rows.iter().next().map(|row| LimitOverrides::from_row(&row)))
+}
+
+/// The limits that apply to `user_id`.
+pub fn limits<C: GenericConnection>(con: &C, defaults: &Limits, user_id: &str) -> Result<Limits> {
+    overrides(con, user_id)
+        .map(|overrides| overrides.map_or(*defaults, |overrides| defaults.overridden_by(&overrides)))
+}
+
+/// Checks whether `user_id` may ask for `amount`, failing with the first rule it breaks.
+pub fn check<C: GenericConnection>(con: &C, defaults: &Limits, home: Currency, user_id: &str, amount: Money,
+                                   currency: Currency) -> Result<()> {
+    let limits = limits(con, defaults, user_id)?;
+    let today = Utc::today().naive_utc();
+    // Only the amount limits need rates, so a request without them doesn't need one for today.
+    let mut rates = Rates::new(con, home);
+
+    if let Some(max) = limits.max_request {
+        let in_home = rates.convert(amount, currency, today)?;
+        if in_home > max {
+            bail!(ErrorKind::LendingPolicyError("max_request".to_string(),
+                                                format!("{} {} is more than the limit of {} {} for a single request",
+                                                        in_home, home, max, home)))
+        }
+    }
+
+    let open = con.query(&format!("SELECT {} FROM requests WHERE user_id = $1;", models::REQUEST_COLUMNS),
+                         &[&user_id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?
+        .iter()
+        .map(|row| models::Request::from_row(&row))
+        .collect::<Result<Vec<models::Request>>>()?
+        .into_iter()
+        .filter(|request| request.status.is_open())
+        .collect::<Vec<models::Request>>();
+
+    if let Some(max) = limits.max_open_loans {
+        if open.len() as i64 >= i64::from(max) {
+            bail!(ErrorKind::LendingPolicyError("max_open_loans".to_string(),
+                                                format!("{} requests are already open, the most allowed is {}",
+                                                        open.len(), max)))
+        }
+    }
+
+    if let Some(max) = limits.max_outstanding {
+        let ids = open.iter().map(|request| request.id).collect::<Vec<i32>>();
+        let repayments = repayment::by_request(con, &ids)?;
+        let mut outstanding = Money::zero();
+        for request in &open {
+            let owed = request.balance(repayments.get(&request.id).map(Vec::as_slice).unwrap_or(&[])).total_owed;
+            outstanding = outstanding + rates.convert(owed, request.currency, today)?;
+        }
+        let in_home = rates.convert(amount, currency, today)?;
+        if outstanding + in_home > max {
+            bail!(ErrorKind::LendingPolicyError("max_outstanding".to_string(),
+                                                format!("{} {} is already outstanding, so another {} {} would be more \
+                                                         than the limit of {} {}", outstanding, home, in_home, home,
+                                                        max, home)))
+        }
+    }
+
+    // Late repayments are recorded by repayment::record.
+    if limits.cooling_off_days > 0 {
+        let rows = con.query("SELECT max(e.created_at) AS last_late FROM request_events e \
+                              JOIN requests r ON r.id = e.request_id \
+                              WHERE r.user_id = $1 AND e.kind = 'late_repayment';", &[&user_id])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        let last_late: Option<DateTime<Utc>> = rows.iter().next().and_then(|row| row.get("last_late"));
+        if let Some(last_late) = last_late {
+            let days = days_between(last_late, Utc::now());
+            if days < i64::from(limits.cooling_off_days) {
+                bail!(ErrorKind::LendingPolicyError("cooling_off".to_string(),
+                                                    format!("a repayment was late {} days ago, new requests can be \
+                                                             made {} days after a late repayment",
+                                                            days, limits.cooling_off_days)))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{File, FileFormat};
+
+    fn config(toml: &str) -> Config {
+        let mut config = Config::new();
+        config.merge(File::from_str(toml, FileFormat::Toml)).unwrap();
+        config
+    }
+
+    fn pence(n: i64) -> Money {
+        Money::from_minor_units(n)
+    }
+
+    fn overrides(max_request: Option<Money>, cooling_off_days: Option<i32>) -> LimitOverrides {
+        LimitOverrides {
+            user_id: "borrower".to_string(),
+            max_outstanding: None,
+            max_request,
+            max_open_loans: None,
+            cooling_off_days,
+            updated_by: "admin".to_string(),
+            updated_at: Utc::now()
+        }
+    }
+
+    #[test]
+    fn no_policy_table_means_no_limits() {
+        assert_eq!(Limits::default(), Limits::from_config(&config(""), Currency::default()).unwrap());
+    }
+
+    #[test]
+    fn limits_are_read_from_the_policy_table() {
+        let limits = Limits::from_config(&config("[policy]\n\
+                                                  max_outstanding = \"1000.00\"\n\
+                                                  max_request = \"250.50\"\n\
+                                                  max_open_loans = 2\n\
+                                                  cooling_off_days = 30\n"), Currency::default()).unwrap();
+        assert_eq!(Limits {
+            max_outstanding: Some(pence(100_000)),
+            max_request: Some(pence(25_050)),
+            max_open_loans: Some(2),
+            cooling_off_days: 30
+        }, limits);
+    }
+
+    #[test]
+    fn invalid_amounts_are_refused() {
+        let home = Currency::default();
+        assert!(Limits::from_config(&config("[policy]\nmax_request = \"lots\"\n"), home).is_err());
+        assert!(Limits::from_config(&config("[policy]\nmax_request = \"$1000\"\n"), home).is_err());
+        assert_eq!(Some(pence(100_000)),
+                   Limits::from_config(&config("[policy]\nmax_request = \"£1000\"\n"), home).unwrap().max_request);
+    }
+
+    #[test]
+    fn counts_must_fit_the_database() {
+        let home = Currency::default();
+        assert!(Limits::from_config(&config("[policy]\nmax_open_loans = -1\n"), home).is_err());
+        assert!(Limits::from_config(&config("[policy]\ncooling_off_days = -30\n"), home).is_err());
+        assert!(Limits::from_config(&config("[policy]\ncooling_off_days = 4294967296\n"), home).is_err());
+        let none_open = Limits::from_config(&config("[policy]\nmax_open_loans = 0\n"), home).unwrap();
+        assert_eq!(Some(0), none_open.max_open_loans);
+    }
+
+    #[test]
+    fn overrides_replace_only_the_limits_they_set() {
+        let defaults = Limits {
+            max_outstanding: Some(pence(100_000)),
+            max_request: Some(pence(10_000)),
+            max_open_loans: Some(2),
+            cooling_off_days: 30
+        };
+        let limits = defaults.overridden_by(&overrides(Some(pence(50_000)), Some(0)));
+        assert_eq!(Limits {
+            max_outstanding: Some(pence(100_000)),
+            max_request: Some(pence(50_000)),
+            max_open_loans: Some(2),
+            cooling_off_days: 0
+        }, limits);
+        assert_eq!(defaults, defaults.overridden_by(&overrides(None, None)));
+    }
+}
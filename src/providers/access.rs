@@ -0,0 +1,278 @@
+//! Which routes need an identity.
+//!
+//! An access policy is an ordered list of rules, each matching a path and optionally a set of
+//! methods. The first rule matching a request decides its access, and requests matching no rule
+//! get the default. Rules from the config are checked before the built in ones, so they only need
+//! to list overrides. Paths are matched a segment at a time: a `prefix` matches the path and
+//! everything under it, while in a `pattern` `*` matches any one segment and a trailing `**` any
+//! number of segments.
+
+use std::str::FromStr;
+
+use iron::method::Method;
+use config::{Config, Value};
+
+use errors::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Public,
+    Authenticated,
+    Admin
+}
+
+impl FromStr for Access {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Access> {
+        match s {
+            "public" => Ok(Access::Public),
+            "authenticated" => Ok(Access::Authenticated),
+            "admin" => Ok(Access::Admin),
+            _ => Err(Error::from(ErrorKind::InvalidConfigTypeError("auth.rules.access".to_string(),
+                                                                   "public, authenticated or admin".to_string())))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Path {
+    Prefix(Vec<String>),
+    Pattern(Vec<String>)
+}
+
+impl Path {
+    fn matches(&self, path: &[&str]) -> bool {
+        match *self {
+            Path::Prefix(ref prefix) => path.len() >= prefix.len() &&
+                prefix.iter().zip(path).all(|(expected, segment)| expected == segment),
+            Path::Pattern(ref pattern) => matches_pattern(pattern, path)
+        }
+    }
+}
+
+fn matches_pattern(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        Some((first, _)) if first == "**" => true,
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path)) => (first == "*" || first == segment) && matches_pattern(rest, path),
+            None => false
+        },
+        None => path.is_empty()
+    }
+}
+
+fn segments(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    path: Path,
+    methods: Option<Vec<Method>>,
+    access: Access
+}
+
+impl Rule {
+    pub fn prefix(prefix: &str, access: Access) -> Rule {
+        Rule {
+            path: Path::Prefix(segments(prefix)),
+            methods: None,
+            access
+        }
+    }
+
+    pub fn pattern(pattern: &str, access: Access) -> Rule {
+        Rule {
+            path: Path::Pattern(segments(pattern)),
+            methods: None,
+            access
+        }
+    }
+
+    /// Only apply the rule to requests using one of `methods`.
+    pub fn methods(mut self, methods: Vec<Method>) -> Rule {
+        self.methods = Some(methods);
+        self
+    }
+
+    fn matches(&self, method: &Method, path: &[&str]) -> bool {
+        self.methods.as_ref().map_or(true, |methods| methods.contains(method)) && self.path.matches(path)
+    }
+
+    fn from_value(value: Value) -> Result<Rule> {
+        let mut table = value.into_table().map_err(|err| Error::from(ErrorKind::ConfigError(err)))?;
+
+        let access = table.remove("access")
+            .ok_or_else(|| Error::from(ErrorKind::MissingConfigValueTableError("access".to_string(),
+                                                                               "auth.rules".to_string())))
+            .and_then(string)
+            .and_then(|access| access.parse())?;
+
+        let rule = match (table.remove("prefix"), table.remove("pattern")) {
+            (Some(prefix), None) => Rule::prefix(&string(prefix)?, access),
+            (None, Some(pattern)) => Rule::pattern(&string(pattern)?, access),
+            _ => bail!(ErrorKind::InvalidConfigTypeError("auth.rules".to_string(),
+                                                         "table with one of prefix or pattern".to_string()))
+        };
+
+        match table.remove("methods") {
+            Some(methods) => methods.into_array()
+                .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?
+                .into_iter()
+                .map(|method| string(method).and_then(|method| method.to_uppercase().parse::<Method>()
+                    .map_err(|_| Error::from(ErrorKind::InvalidConfigTypeError("auth.rules.methods".to_string(),
+                                                                               "HTTP method".to_string())))))
+                .collect::<Result<Vec<Method>>>()
+                .map(|methods| rule.methods(methods)),
+            None => Ok(rule)
+        }
+    }
+}
+
+fn string(value: Value) -> Result<String> {
+    value.into_str().map_err(|err| Error::from(ErrorKind::ConfigError(err)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessPolicy {
+    rules: Vec<Rule>,
+    default: Access
+}
+
+impl Default for AccessPolicy {
+    /// The login flow and the static site are public, managing loans and users is for admins, and
+    /// everything else needs an identity.
+    fn default() -> AccessPolicy {
+        AccessPolicy {
+            rules: vec![
+                Rule::pattern("/request/*/approve", Access::Admin).methods(vec![Method::Post]),
+                Rule::pattern("/request/*/reject", Access::Admin).methods(vec![Method::Post]),
+                Rule::pattern("/request/*/disburse", Access::Admin).methods(vec![Method::Post]),
+                Rule::pattern("/users", Access::Admin).methods(vec![Method::Get]),
+                Rule::pattern("/users/*/roles/**", Access::Admin).methods(vec![Method::Post, Method::Delete]),
+                Rule::prefix("/exchange-rates", Access::Admin).methods(vec![Method::Post]),
+                Rule::prefix("/limits", Access::Admin).methods(vec![Method::Post, Method::Delete]),
+                Rule::prefix("/login", Access::Public),
+                Rule::pattern("/", Access::Public).methods(vec![Method::Get, Method::Head]),
+                Rule::pattern("/index.html", Access::Public).methods(vec![Method::Get, Method::Head]),
+                Rule::prefix("/css", Access::Public).methods(vec![Method::Get, Method::Head]),
+                Rule::prefix("/js", Access::Public).methods(vec![Method::Get, Method::Head]),
+                Rule::prefix("/new", Access::Public).methods(vec![Method::Get, Method::Head])
+            ],
+            default: Access::Authenticated
+        }
+    }
+}
+
+impl AccessPolicy {
+    pub fn new(rules: Vec<Rule>, default: Access) -> AccessPolicy {
+        AccessPolicy {
+            rules,
+            default
+        }
+    }
+
+    /// The default policy with any `rules` in the `auth` table of the config checked first.
+    pub fn from_config(config: &Config) -> Result<AccessPolicy> {
+        let mut table = match config.get_table("auth") {
+            Ok(table) => table,
+            Err(_) => return Ok(AccessPolicy::default())
+        };
+
+        let default = match table.remove("default") {
+            Some(v) => string(v)?.parse()?,
+            None => Access::Authenticated
+        };
+        let mut rules = match table.remove("rules") {
+            Some(rules) => rules.into_array()
+                .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?
+                .into_iter()
+                .map(Rule::from_value)
+                .collect::<Result<Vec<Rule>>>()?,
+            None => Vec::new()
+        };
+        rules.extend(AccessPolicy::default().rules);
+
+        Ok(AccessPolicy::new(rules, default))
+    }
+
+    pub fn access(&self, method: &Method, path: &str) -> Access {
+        let path = path.split('/').filter(|segment| !segment.is_empty()).collect::<Vec<&str>>();
+        self.rules.iter()
+            .find(|rule| rule.matches(method, &path))
+            .map_or(self.default, |rule| rule.access)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{File, FileFormat};
+
+    fn policy() -> AccessPolicy {
+        let mut policy = AccessPolicy::default();
+        policy.rules.insert(0, Rule::pattern("/request/*/approve", Access::Admin).methods(vec![Method::Post]));
+        policy.rules.insert(1, Rule::pattern("/limits/*/**", Access::Admin)
+            .methods(vec![Method::Post, Method::Delete]));
+        policy
+    }
+
+    #[test]
+    fn login_and_static_assets_are_public() {
+        let policy = policy();
+        assert_eq!(Access::Public, policy.access(&Method::Get, "/"));
+        assert_eq!(Access::Public, policy.access(&Method::Get, "/index.html"));
+        assert_eq!(Access::Public, policy.access(&Method::Get, "/css/bootstrap.min.css"));
+        assert_eq!(Access::Public, policy.access(&Method::Post, "/login"));
+        assert_eq!(Access::Public, policy.access(&Method::Get, "/login/callback"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Post, "/css/bootstrap.min.css"));
+    }
+
+    #[test]
+    fn everything_else_needs_an_identity() {
+        let policy = policy();
+        assert_eq!(Access::Authenticated, policy.access(&Method::Get, "/request"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Get, "/request/1"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Get, "/loginx"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Get, "/index.html/x"));
+    }
+
+    #[test]
+    fn patterns_match_whole_segments_and_methods() {
+        let policy = policy();
+        assert_eq!(Access::Admin, policy.access(&Method::Post, "/request/12/approve"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Get, "/request/12/approve"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Post, "/request/12/cancel"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Post, "/request/approve"));
+        assert_eq!(Access::Admin, policy.access(&Method::Delete, "/limits/alice"));
+        assert_eq!(Access::Admin, policy.access(&Method::Post, "/limits/alice/extra"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Get, "/limits/alice"));
+    }
+
+    #[test]
+    fn managing_loans_and_users_is_for_admins_by_default() {
+        let policy = AccessPolicy::default();
+        assert_eq!(Access::Admin, policy.access(&Method::Post, "/request/12/disburse"));
+        assert_eq!(Access::Admin, policy.access(&Method::Get, "/users"));
+        assert_eq!(Access::Admin, policy.access(&Method::Delete, "/users/alice/roles/admin"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Get, "/users/alice"));
+    }
+
+    #[test]
+    fn configured_rules_override_the_defaults() {
+        let mut config = Config::new();
+        config.merge(File::from_str("[auth]\n\
+                                     [[auth.rules]]\n\
+                                     prefix = \"/users\"\n\
+                                     access = \"public\"\n", FileFormat::Toml)).unwrap();
+        let policy = AccessPolicy::from_config(&config).unwrap();
+        assert_eq!(Access::Public, policy.access(&Method::Get, "/users"));
+        assert_eq!(Access::Admin, policy.access(&Method::Post, "/request/12/approve"));
+        assert_eq!(Access::Public, policy.access(&Method::Get, "/login"));
+        assert_eq!(Access::Authenticated, policy.access(&Method::Get, "/request"));
+    }
+}
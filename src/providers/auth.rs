@@ -1,106 +1,119 @@
-use std::collections::HashSet;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use iron::prelude::*;
-use iron::BeforeMiddleware;
-use hyper::Client;
-use hyper::status::StatusCode;
-use base64;
-use bodyparser::Struct;
-use serde_json;
-use jwt::{self, Validation, Header};
+use iron::{BeforeMiddleware, status};
+use chrono::Utc;
 
 use errors::*;
-use google::{CachedKeys, CachedDiscovery, Key};
-use models::User;
+use params::bearer_token;
+use personal_tokens::{self, Scope};
+use roles;
+use super::access::{Access, AccessPolicy};
+use super::database::Database;
+use super::identity::AuthenticatedUser;
+use super::session::{Device, Sessions};
 
 pub struct Auth {
-    paths: HashSet<String>,
-    keys: Arc<Mutex<CachedKeys>>,
-    discovery: Arc<Mutex<CachedDiscovery>>,
-    client: Client
-}
-
-#[derive(Debug, Clone, Deserialize)]
-struct GoogleToken {
-    iss: String,
-    at_hash: String,
-    email_verified: String,
-    sub: String,
-    azp: String,
-    email: String,
-    aud: String,
-    iat: i64,
-    exp: i64,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-struct UserData {
-    user: User,
-    jwt: String
+    policy: AccessPolicy,
+    sessions: Arc<Sessions>
 }
 
 impl Auth {
-    pub fn new(client: Client, paths: HashSet<String>) -> Result<Auth> {
-        CachedDiscovery::new(&client)
-            .and_then(|mut discovery| discovery.discovery(&client)
-                .and_then(|disc| CachedKeys::new(&client, disc))
-                    .map(|keys| Auth {
-                        client,
-                        paths,
-                        keys: Arc::new(Mutex::new(keys)),
-                        discovery: Arc::new(Mutex::new(discovery))
-                    })
+    pub fn new(policy: AccessPolicy, sessions: Arc<Sessions>) -> Auth {
+        Auth {
+            policy,
+            sessions
+        }
+    }
 
-            ).map_err(|err| Error::from(ErrorKind::GoogleError(err))
-        )
+    /// ID tokens are only accepted at /login, so every other route needs either a session token or
+    /// a personal token. Either way the user's roles are read from the database, so a role taken
+    /// away stops working at once.
+    fn authenticate(&self, req: &Request) -> Result<AuthenticatedUser> {
+        let token = bearer_token(req)?;
+        if token.starts_with(personal_tokens::PREFIX) {
+            let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+            personal_tokens::authenticate(&*con, &token, Utc::today().naive_utc())
+        } else {
+            let claims = self.sessions.verify(&token)?;
+            let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+            self.sessions.touch(&*con, claims.sid, &Device::from_request(req))?;
+            roles::roles(&*con, &claims.sub).map(|roles| AuthenticatedUser::from_session(claims, roles))
+        }
     }
 }
 
 impl BeforeMiddleware for Auth {
     fn before(&self, req: &mut Request) -> IronResult<()> {
-        if self.paths.contains(req.url.as_ref().path()) {
-            self.discovery.lock()
-                .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "CachedDiscovery".to_string())))
-                .and_then(|mut cached_disc| cached_disc.discovery(&self.client)
-                    .map_err(|err| Error::from(ErrorKind::GoogleError(err)))
-                    .and_then(|disc| self.keys.lock()
-                        .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "CachedKeys".to_string())))
-                        .and_then(|mut cached| cached.keys(&self.client, disc)
-                            .map_err(|err| Error::from(ErrorKind::GoogleError(err)))
-                            .and_then(|keys| req.get::<Struct<UserData>>()
-                                .map_err(|err| Error::from(ErrorKind::RequestBody2Error(err)))
-                                .and_then(|data| data.ok_or_else(|| Error::from(ErrorKind::MissingRequestError)))
-                                .and_then(|user_data| get_key(&user_data.jwt, keys)
-                                    .and_then(|key| base64::decode(&key.n)
-                                        .map_err(ErrorKind::Base64Error)
-                                        .and_then(|secret| {
-                                            let mut validation = Validation::default();
-                                            validation.iss = Some("accounts.google.com".to_string());
-                                            jwt::decode::<GoogleToken>(&user_data.jwt, &secret, &validation)
-                                                .map_err(ErrorKind::JwtError)
-                                        }).map(|_| ())
-                                    ).map_err(Error::from)
-                                ).map_err(Error::from)
-                            )
-                        )
-                    )
-                ).map_err(|err| IronError {
-                error: Box::new(Error::from(err)),
-                response: Response::with((StatusCode::ImATeapot, "Teapot"))
-            })
-        } else {
-            Ok(())
+        let access = self.policy.access(&req.method, req.url.as_ref().path());
+        if access == Access::Public {
+            return Ok(())
+        }
+
+        let user = self.authenticate(req).map_err(|err| match *err.kind() {
+            ErrorKind::InternalServerError => IronError::new(err, status::InternalServerError),
+            _ => unauthorized(err)
+        })?;
+        if user.scopes.is_some() {
+            match personal_tokens::required_scope(&req.method, req.url.as_ref().path()) {
+                Some(scope) if user.has_scope(scope) => {},
+                scope => return Err(insufficient_scope(scope))
+            }
+        }
+        if access == Access::Admin && !user.is_admin() {
+            return Err(IronError::new(Error::from(ErrorKind::ForbiddenError), status::Forbidden))
         }
+        req.extensions.insert::<AuthenticatedUser>(user);
+        Ok(())
     }
 }
 
-fn get_key<'a>(token: &str, keys: &'a [Key]) -> ::std::result::Result<&'a Key, ErrorKind> {
-    serde_json::from_str::<Header>(token.split('.').next().unwrap())
-        .map_err(ErrorKind::JsonError)
-        .and_then(|head| head.kid.ok_or_else(|| ErrorKind::MissingKidGoogleTokenError))
-        .and_then(|kid| keys.iter()
-            .find(|key| key.kid == kid)
-            .ok_or_else(|| ErrorKind::NoValidKeyGoogleError))
+/// A 401 for a request that couldn't be authenticated because of `err`.
+pub fn unauthorized(err: Error) -> IronError {
+    let challenge = challenge(err.kind());
+    let mut err = IronError::new(Error::with_chain(err, ErrorKind::UnauthorizedError), status::Unauthorized);
+    err.response.headers.set_raw("WWW-Authenticate", vec![challenge.into_bytes()]);
+    err
+}
+
+/// A 403 for a personal token used without the `scope` the route needs, or on a route personal
+/// tokens can't be used on at all.
+fn insufficient_scope(scope: Option<Scope>) -> IronError {
+    let (kind, challenge) = match scope {
+        Some(scope) => (ErrorKind::InsufficientScopeError(scope.to_string()),
+                        format!("Bearer realm=\"riostu\", error=\"insufficient_scope\", scope=\"{}\"", scope)),
+        None => (ErrorKind::PersonalTokenNotAllowedError,
+                 "Bearer realm=\"riostu\", error=\"insufficient_scope\"".to_string())
+    };
+    let mut err = IronError::new(Error::from(kind), status::Forbidden);
+    err.response.headers.set_raw("WWW-Authenticate", vec![challenge.into_bytes()]);
+    err
+}
+
+/// The `WWW-Authenticate` challenge explaining why a request couldn't be authenticated, in the
+/// form RFC 6750 gives for bearer tokens.
+fn challenge(kind: &ErrorKind) -> String {
+    // Quoted strings can't hold a double quote or backslash without escaping, so leave them out.
+    let description = kind.to_string().replace(|c| c == '"' || c == '\\', "");
+    match *kind {
+        ErrorKind::MissingCredentialsError => "Bearer realm=\"riostu\"".to_string(),
+        ErrorKind::MalformedCredentialsError =>
+            format!("Bearer realm=\"riostu\", error=\"invalid_request\", error_description=\"{}\"", description),
+        _ => format!("Bearer realm=\"riostu\", error=\"invalid_token\", error_description=\"{}\"", description)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenges_say_why_a_request_was_refused() {
+        assert_eq!("Bearer realm=\"riostu\"", challenge(&ErrorKind::MissingCredentialsError));
+        assert_eq!("Bearer realm=\"riostu\", error=\"invalid_request\", \
+                    error_description=\"Authorization header must hold a bearer token!\"",
+                   challenge(&ErrorKind::MalformedCredentialsError));
+        assert_eq!("Bearer realm=\"riostu\", error=\"invalid_token\", error_description=\"Token has expired!\"",
+                   challenge(&ErrorKind::ExpiredTokenError));
+    }
+}
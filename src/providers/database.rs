@@ -1,6 +1,6 @@
 use iron::prelude::*;
 use iron::{typemap, BeforeMiddleware};
-use r2d2::{Config, Pool};
+use r2d2::{Config, Pool, PooledConnection};
 use r2d2_postgres::{TlsMode, PostgresConnectionManager};
 
 use std::sync::Arc;
@@ -63,6 +63,17 @@ impl Database {
                             })
                     }).unwrap_or_else(Config::default);*/
     }
+
+    /// A connection from the pool, for use outside a request.
+    pub fn connect(&self) -> Result<PooledConnection<PostgresConnectionManager>> {
+        self.pool.get().map_err(|err| Error::from(ErrorKind::PoolTimeoutError(err)))
+    }
+
+    pub fn connection(req: &Request) -> Result<PooledConnection<PostgresConnectionManager>> {
+        req.extensions.get::<Database>()
+            .ok_or_else(|| Error::from(ErrorKind::MissingDatabaseConnectionError))
+            .and_then(|pool| pool.get().map_err(|err| Error::from(ErrorKind::PoolTimeoutError(err))))
+    }
 }
 
 impl BeforeMiddleware for Database {
@@ -17,14 +17,39 @@ impl AfterMiddleware for ErrorCapture {
             warn!(log, "Error during handling"; "url" => req.url.as_ref().as_str(), "desc" => err.description());
             trace!(log, "{:?}", err)
         }
-        Ok(match err.error.deref().downcast::<::errors::Error>().map(|e| e.deref()) {
+        let mut response = match err.error.deref().downcast::<::errors::Error>().map(|e| e.deref()) {
             Some(error) => match *error {
                 ErrorKind::BadRequestError => Response::with((status::BadRequest, info)),
+                ErrorKind::UnauthorizedError => Response::with((status::Unauthorized, info)),
+                ErrorKind::InvalidRefreshTokenError => Response::with((status::Unauthorized, info)),
+                ErrorKind::ForbiddenError => Response::with((status::Forbidden, info)),
+                ErrorKind::RegistrationClosedError(_) => Response::with((status::Forbidden, info)),
+                ErrorKind::InsufficientScopeError(_) |
+                ErrorKind::PersonalTokenNotAllowedError => Response::with((status::Forbidden, info)),
+                ErrorKind::NotFoundError(_) => Response::with((status::NotFound, info)),
+                ErrorKind::InvalidTransitionError(_, _) => Response::with((status::Conflict, info)),
+                ErrorKind::NotRepayableError(_) => Response::with((status::Conflict, info)),
+                ErrorKind::LastAdminError => Response::with((status::Conflict, info)),
+                ErrorKind::OverpaymentError(_, _) => Response::with((status::UnprocessableEntity, info)),
+                ErrorKind::LendingPolicyError(_, _) => Response::with((status::UnprocessableEntity, info)),
+                ErrorKind::AmountParseError |
+                ErrorKind::AmountNotPositiveError |
+                ErrorKind::AmountTooLargeError(_) => Response::with((status::BadRequest, info)),
+                ErrorKind::CurrencyParseError(_) |
+                ErrorKind::RateParseError(_) => Response::with((status::BadRequest, info)),
+                ErrorKind::CurrencyMismatchError(_, _) => Response::with((status::UnprocessableEntity, info)),
+                ErrorKind::MissingExchangeRateError(_, _) => Response::with((status::Conflict, info)),
+                ErrorKind::UnsupportedMediaTypeError(_) => Response::with((status::UnsupportedMediaType, info)),
                 _ => Response::with((status::InternalServerError, info))
             },
             None => {
                 Response::with((status::InternalServerError, info))
             }
-        })
+        };
+        // Keep the challenge Auth sent with a 401 so clients know how to authenticate.
+        if let Some(challenge) = err.response.headers.get_raw("WWW-Authenticate") {
+            response.headers.set_raw("WWW-Authenticate", challenge.to_vec());
+        }
+        Ok(response)
     }
 }
\ No newline at end of file
@@ -0,0 +1,369 @@
+//! Verification of RS256 signed OpenID Connect ID tokens against a provider's published keys.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::Utc;
+use config::Value;
+use serde::{Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+use serde_json;
+use jwt::{self, Algorithm, Validation};
+
+use errors::*;
+use google::{self, Key};
+
+/// Google documents both forms as valid values of `iss`.
+pub const GOOGLE_ISSUERS: &[&str] = &["accounts.google.com", "https://accounts.google.com"];
+
+const DEFAULT_LEEWAY_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>)
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match *self {
+            Audience::One(ref aud) => aud == client_id,
+            Audience::Many(ref auds) => auds.iter().any(|aud| aud == client_id)
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Audience::One(_) => 1,
+            Audience::Many(ref auds) => auds.len()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    pub iss: String,
+    pub sub: String,
+    aud: Audience,
+    pub azp: Option<String>,
+    pub email: Option<String>,
+    #[serde(default, deserialize_with = "bool_or_string")]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub iat: i64,
+    pub exp: i64
+}
+
+// Google has sent email_verified both as a boolean and as the string "true".
+fn bool_or_string<'de, D>(deserializer: D) -> ::std::result::Result<bool, D::Error> where D: Deserializer<'de> {
+    struct BoolVisitor;
+
+    impl<'de> Visitor<'de> for BoolVisitor {
+        type Value = bool;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a boolean or \"true\" or \"false\"")
+        }
+
+        fn visit_bool<E>(self, b: bool) -> ::std::result::Result<bool, E> where E: de::Error {
+            Ok(b)
+        }
+
+        fn visit_str<E>(self, s: &str) -> ::std::result::Result<bool, E> where E: de::Error {
+            match s {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(E::invalid_value(de::Unexpected::Str(s), &self))
+            }
+        }
+    }
+
+    deserializer.deserialize_any(BoolVisitor)
+}
+
+pub struct Verifier {
+    issuers: Vec<String>,
+    client_ids: Vec<String>,
+    domains: Vec<String>,
+    leeway: i64
+}
+
+impl Verifier {
+    /// A verifier for tokens from the first of `issuers`, which may also use the others as `iss`.
+    pub fn new(issuers: Vec<String>, client_ids: Vec<String>, leeway: i64) -> Verifier {
+        Verifier {
+            issuers,
+            client_ids,
+            domains: Vec::new(),
+            leeway
+        }
+    }
+
+    /// Only accepts email addresses in `domains`, or any address if it is empty.
+    pub fn domains(mut self, domains: Vec<String>) -> Verifier {
+        self.domains = domains.into_iter().map(|domain| domain.to_lowercase()).collect();
+        self
+    }
+
+    /// A verifier for an entry of the `identity_providers` array of the config.
+    pub fn from_table(mut table: HashMap<String, Value>) -> Result<Verifier> {
+        let issuer = table.remove("issuer")
+            .ok_or_else(|| Error::from(ErrorKind::MissingConfigValueTableError("issuer".to_string(),
+                                                                               "identity_providers".to_string())))
+            .and_then(string)?;
+        let client_ids = table.remove("client_ids")
+            .ok_or_else(|| Error::from(ErrorKind::MissingConfigValueTableError("client_ids".to_string(),
+                                                                               "identity_providers".to_string())))
+            .and_then(strings)?;
+        let domains = match table.remove("domains") {
+            Some(domains) => strings(domains)?,
+            None => Vec::new()
+        };
+        let leeway = match table.remove("leeway_secs") {
+            Some(v) => v.into_int().map_err(|err| Error::from(ErrorKind::ConfigError(err)))?,
+            None => DEFAULT_LEEWAY_SECS
+        };
+
+        let mut issuers = vec![issuer.clone()];
+        if GOOGLE_ISSUERS.contains(&issuer.as_str()) {
+            issuers.extend(GOOGLE_ISSUERS.iter().filter(|iss| **iss != issuer).map(|iss| iss.to_string()));
+        }
+        Ok(Verifier::new(issuers, client_ids, leeway).domains(domains))
+    }
+
+    /// The issuer whose discovery document and keys tokens are verified against.
+    pub fn issuer(&self) -> &str {
+        &self.issuers[0]
+    }
+
+    pub fn accepts_issuer(&self, iss: &str) -> bool {
+        self.issuers.iter().any(|issuer| issuer == iss)
+    }
+
+    /// Verifies the signature of `token` with the matching key from `keys`, then checks its claims.
+    pub fn verify(&self, token: &str, keys: &[Key]) -> Result<Claims> {
+        let header = jwt::decode_header(token).map_err(|err| Error::from(ErrorKind::JwtError(err)))?;
+        let kid = header.kid.ok_or_else(|| Error::from(ErrorKind::MissingKidGoogleTokenError))?;
+        let key = keys.iter()
+            .find(|key| key.kid == kid)
+            .ok_or_else(|| Error::from(ErrorKind::NoValidKeyGoogleError))?;
+
+        // Only accept the algorithm the key was published for, so a token can't pick a weaker one.
+        // Keys without an alg, as Microsoft publishes them, are taken to be RS256 keys.
+        match key.alg {
+            Some(ref alg) if alg != "RS256" => bail!(ErrorKind::UnsupportedAlgorithmError(alg.clone())),
+            _ => {}
+        }
+        if header.alg != Algorithm::RS256 {
+            bail!(ErrorKind::UnsupportedAlgorithmError(format!("{:?}", header.alg)))
+        }
+
+        let der = key.public_key_der().map_err(|err| Error::from(ErrorKind::GoogleError(err)))?;
+        let mut validation = Validation::default();
+        validation.algorithms = vec![Algorithm::RS256];
+        validation.leeway = self.leeway;
+        let claims = jwt::decode::<Claims>(token, &der, &validation)
+            .map_err(|err| Error::from(ErrorKind::JwtError(err)))?
+            .claims;
+
+        self.check(&claims, Utc::now().timestamp())?;
+        Ok(claims)
+    }
+
+    fn check(&self, claims: &Claims, now: i64) -> Result<()> {
+        if !self.issuers.contains(&claims.iss) {
+            bail!(ErrorKind::InvalidIssuerError(claims.iss.clone()))
+        }
+        if !self.client_ids.iter().any(|id| claims.aud.contains(id)) {
+            bail!(ErrorKind::InvalidAudienceError)
+        }
+        // With several audiences the authorised party says which of them the token was issued to.
+        match claims.azp {
+            Some(ref azp) if !self.client_ids.contains(azp) => bail!(ErrorKind::InvalidAudienceError),
+            None if claims.aud.len() > 1 => bail!(ErrorKind::InvalidAudienceError),
+            _ => {}
+        }
+        if claims.exp + self.leeway <= now {
+            bail!(ErrorKind::ExpiredTokenError)
+        }
+        if claims.iat - self.leeway > now {
+            bail!(ErrorKind::TokenNotYetValidError)
+        }
+        if !claims.email_verified {
+            bail!(ErrorKind::UnverifiedEmailError(claims.email.clone().unwrap_or_default()))
+        }
+        if !self.domains.is_empty() {
+            let email = claims.email.clone().unwrap_or_default().to_lowercase();
+            let domain = email.rsplitn(2, '@').next().unwrap_or("");
+            if !email.contains('@') || !self.domains.iter().any(|allowed| allowed == domain) {
+                bail!(ErrorKind::EmailDomainNotAllowedError(email))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `iss` of `token`, read without checking its signature so the provider that issued it can
+/// be chosen to verify it.
+pub fn unverified_issuer(token: &str) -> Result<String> {
+    #[derive(Deserialize)]
+    struct Issuer {
+        iss: String
+    }
+
+    let payload = token.split('.').nth(1).ok_or_else(|| Error::from(ErrorKind::MalformedCredentialsError))?;
+    let json = google::decode_url_safe(payload).map_err(|err| Error::from(ErrorKind::GoogleError(err)))?;
+    serde_json::from_slice::<Issuer>(&json)
+        .map(|claims| claims.iss)
+        .map_err(|err| Error::from(ErrorKind::JsonError(err)))
+}
+
+fn string(value: Value) -> Result<String> {
+    value.into_str().map_err(|err| Error::from(ErrorKind::ConfigError(err)))
+}
+
+fn strings(value: Value) -> Result<Vec<String>> {
+    value.into_array()
+        .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?
+        .into_iter()
+        .map(string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jwt::Header;
+
+    const CLIENT_ID: &str = "riostu.apps.googleusercontent.com";
+
+    #[derive(Debug, Serialize)]
+    struct TestClaims {
+        iss: &'static str,
+        sub: &'static str,
+        aud: Vec<&'static str>,
+        azp: Option<&'static str>,
+        email: &'static str,
+        email_verified: bool,
+        iat: i64,
+        exp: i64
+    }
+
+    impl Default for TestClaims {
+        fn default() -> TestClaims {
+            let now = Utc::now().timestamp();
+            TestClaims {
+                iss: "https://accounts.google.com",
+                sub: "110169484474386276334",
+                aud: vec![CLIENT_ID],
+                azp: Some(CLIENT_ID),
+                email: "borrower@example.com",
+                email_verified: true,
+                iat: now,
+                exp: now + 3600
+            }
+        }
+    }
+
+    // testdata holds a locally generated key pair, with the public half as a JWK.
+    fn keys() -> Vec<Key> {
+        vec![serde_json::from_str(include_str!("../../testdata/rsa_public.jwk.json")).unwrap()]
+    }
+
+    fn sign(claims: &TestClaims) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test".to_string());
+        jwt::encode(&header, claims, include_bytes!("../../testdata/rsa_private.der")).unwrap()
+    }
+
+    fn verifier() -> Verifier {
+        Verifier::new(GOOGLE_ISSUERS.iter().map(|iss| iss.to_string()).collect(), vec![CLIENT_ID.to_string()], 60)
+    }
+
+    fn verify(claims: &TestClaims) -> Result<Claims> {
+        verifier().verify(&sign(claims), &keys())
+    }
+
+    #[test]
+    fn accepts_a_valid_token_from_either_issuer() {
+        let claims = verify(&TestClaims::default()).unwrap();
+        assert_eq!("110169484474386276334", claims.sub);
+        assert_eq!(Some("borrower@example.com".to_string()), claims.email);
+        assert!(verify(&TestClaims { iss: "accounts.google.com", ..TestClaims::default() }).is_ok());
+        assert!(verify(&TestClaims { iss: "https://evil.example.com", ..TestClaims::default() }).is_err());
+    }
+
+    #[test]
+    fn rejects_a_token_signed_by_another_key() {
+        let token = sign(&TestClaims::default());
+        let mut keys = keys();
+        keys[0].n = keys[0].n.chars().rev().collect();
+        assert!(verifier().verify(&token, &keys).is_err());
+    }
+
+    #[test]
+    fn rejects_an_unknown_kid() {
+        let mut keys = keys();
+        keys[0].kid = "other".to_string();
+        assert!(verifier().verify(&sign(&TestClaims::default()), &keys).is_err());
+    }
+
+    #[test]
+    fn pins_the_algorithm_to_the_key() {
+        let mut hmac_keys = keys();
+        hmac_keys[0].alg = Some("HS256".to_string());
+        assert!(verifier().verify(&sign(&TestClaims::default()), &hmac_keys).is_err());
+
+        // The public modulus used as an HMAC secret, as the old verification did.
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test".to_string());
+        let token = jwt::encode(&header, &TestClaims::default(), keys()[0].n.as_bytes()).unwrap();
+        assert!(verifier().verify(&token, &keys()).is_err());
+    }
+
+    #[test]
+    fn checks_the_audience_and_authorised_party() {
+        assert!(verify(&TestClaims { aud: vec!["someone-else"], azp: None, ..TestClaims::default() }).is_err());
+        assert!(verify(&TestClaims { azp: Some("someone-else"), ..TestClaims::default() }).is_err());
+        assert!(verify(&TestClaims { aud: vec![CLIENT_ID, "other"], azp: None, ..TestClaims::default() }).is_err());
+        assert!(verify(&TestClaims { aud: vec![CLIENT_ID, "other"], ..TestClaims::default() }).is_ok());
+        assert!(verify(&TestClaims { azp: None, ..TestClaims::default() }).is_ok());
+    }
+
+    #[test]
+    fn enforces_expiry_and_issue_time_with_leeway() {
+        let now = Utc::now().timestamp();
+        assert!(verify(&TestClaims { iat: now - 7200, exp: now - 30, ..TestClaims::default() }).is_ok());
+        assert!(verify(&TestClaims { iat: now - 7200, exp: now - 120, ..TestClaims::default() }).is_err());
+        assert!(verify(&TestClaims { iat: now + 30, ..TestClaims::default() }).is_ok());
+        assert!(verify(&TestClaims { iat: now + 120, ..TestClaims::default() }).is_err());
+    }
+
+    #[test]
+    fn requires_a_verified_email() {
+        assert!(verify(&TestClaims { email_verified: false, ..TestClaims::default() }).is_err());
+    }
+
+    #[test]
+    fn limits_email_addresses_to_the_allowed_domains() {
+        let verifier = verifier().domains(vec!["Example.com".to_string()]);
+        assert!(verifier.verify(&sign(&TestClaims::default()), &keys()).is_ok());
+        let other = TestClaims { email: "borrower@evil-example.com", ..TestClaims::default() };
+        assert!(verifier.verify(&sign(&other), &keys()).is_err());
+    }
+
+    #[test]
+    fn reads_the_issuer_before_verifying() {
+        let token = sign(&TestClaims { iss: "https://keycloak.example.com/realms/riostu", ..TestClaims::default() });
+        assert_eq!("https://keycloak.example.com/realms/riostu", unverified_issuer(&token).unwrap());
+        assert!(unverified_issuer("not-a-jwt").is_err());
+    }
+
+    #[test]
+    fn reads_email_verified_as_a_string() {
+        let claims: Claims = serde_json::from_str(r#"{"iss": "accounts.google.com", "sub": "1", "aud": "a",
+                                                      "email_verified": "true", "iat": 0, "exp": 1}"#).unwrap();
+        assert!(claims.email_verified);
+    }
+}
@@ -0,0 +1,130 @@
+use iron::prelude::*;
+use iron::typemap;
+
+use errors::*;
+use roles::Role;
+use personal_tokens::Scope;
+use super::session::SessionClaims;
+
+/// The user a request was made by, put in the request extensions by `Auth` once their token has
+/// been verified.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedUser {
+    pub subject: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub roles: Vec<Role>,
+    /// What a personal token may be used for. Sessions aren't limited by scope.
+    pub scopes: Option<Vec<Scope>>,
+    /// The session a session token belongs to. Personal tokens have none.
+    pub session: Option<i32>
+}
+
+impl typemap::Key for AuthenticatedUser {
+    type Value = AuthenticatedUser;
+}
+
+impl AuthenticatedUser {
+    /// The user of a verified session, who currently has `roles`.
+    pub fn from_session(claims: SessionClaims, roles: Vec<Role>) -> AuthenticatedUser {
+        AuthenticatedUser {
+            subject: claims.sub,
+            email: Some(claims.email),
+            name: Some(claims.name),
+            roles,
+            scopes: None,
+            session: Some(claims.sid)
+        }
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.has_role(Role::Admin)
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.as_ref().map_or(true, |scopes| scopes.contains(&scope))
+    }
+
+    /// Whether the user can see every borrower's requests rather than only their own.
+    pub fn can_view_all(&self) -> bool {
+        self.has_role(Role::Admin) || self.has_role(Role::Auditor)
+    }
+
+    /// The user `Auth` verified for `req`. Routes the access policy makes public have no user.
+    pub fn from_request(req: &Request) -> Result<AuthenticatedUser> {
+        AuthenticatedUser::from_extensions(&req.extensions)
+    }
+
+    fn from_extensions(extensions: &typemap::TypeMap) -> Result<AuthenticatedUser> {
+        extensions.get::<AuthenticatedUser>()
+            .cloned()
+            .ok_or_else(|| Error::from(ErrorKind::UnauthorizedError))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(roles: Vec<Role>) -> AuthenticatedUser {
+        AuthenticatedUser::from_session(SessionClaims {
+            iss: "riostu".to_string(),
+            sub: "alice".to_string(),
+            sid: 7,
+            email: "alice@example.com".to_string(),
+            name: "Alice".to_string(),
+            iat: 0,
+            exp: 900
+        }, roles)
+    }
+
+    #[test]
+    fn sessions_carry_their_roles_and_every_scope() {
+        let user = session(vec![Role::Borrower]);
+        assert_eq!("alice", user.subject);
+        assert_eq!(Some(7), user.session);
+        assert!(user.has_role(Role::Borrower));
+        assert!(!user.is_admin());
+        assert!(!user.can_view_all());
+        assert!(user.has_scope(Scope::RequestsWrite));
+    }
+
+    #[test]
+    fn admins_and_auditors_see_every_request() {
+        let admin = session(vec![Role::Admin]);
+        assert!(admin.is_admin());
+        assert!(admin.can_view_all());
+
+        let auditor = session(vec![Role::Auditor]);
+        assert!(!auditor.is_admin());
+        assert!(auditor.can_view_all());
+    }
+
+    #[test]
+    fn personal_tokens_are_limited_to_their_scopes() {
+        let user = AuthenticatedUser {
+            scopes: Some(vec![Scope::RequestsRead]),
+            session: None,
+            ..session(vec![Role::Borrower])
+        };
+        assert!(user.has_scope(Scope::RequestsRead));
+        assert!(!user.has_scope(Scope::RequestsWrite));
+    }
+
+    #[test]
+    fn requests_without_a_user_are_unauthorized() {
+        let mut extensions = typemap::TypeMap::new();
+        match *AuthenticatedUser::from_extensions(&extensions).unwrap_err().kind() {
+            ErrorKind::UnauthorizedError => {},
+            ref kind => panic!("Expected unauthorized, got {:?}", kind)
+        }
+
+        let user = session(vec![Role::Borrower]);
+        extensions.insert::<AuthenticatedUser>(user.clone());
+        assert_eq!(user, AuthenticatedUser::from_extensions(&extensions).unwrap());
+    }
+}
@@ -3,9 +3,19 @@ mod errorcapture;
 mod logging;
 mod monitoring;
 mod auth;
+mod access;
+mod id_token;
+mod identity;
+mod provider;
+mod session;
 
 pub use self::database::Database;
 pub use self::errorcapture::ErrorCapture;
 pub use self::logging::Log;
 pub use self::monitoring::Monitoring;
-pub use self::auth::Auth;
\ No newline at end of file
+pub use self::auth::{Auth, unauthorized};
+pub use self::access::{Access, AccessPolicy, Rule};
+pub use self::id_token::{Claims, Verifier};
+pub use self::identity::AuthenticatedUser;
+pub use self::provider::{IdentityProvider, IdentityProviders};
+pub use self::session::{Device, Sessions, SessionTokens, hash, random_token};
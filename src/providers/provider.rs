@@ -0,0 +1,440 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hyper::Client;
+use config::Config;
+use chrono;
+use slog::Logger;
+
+use errors::*;
+use google::{self, CachedKeys, CachedDiscovery};
+use super::id_token::{self, Claims, Verifier};
+
+/// How long before they expire the discovery document and keys are refreshed.
+const REFRESH_AHEAD_SECS: i64 = 5 * 60;
+/// How often the background refresh checks what is about to expire.
+const REFRESH_CHECK_SECS: u64 = 60;
+/// The least time between two refreshes of the keys for tokens signed with an unknown key.
+const FORCED_REFRESH_SECS: u64 = 60;
+
+/// The files an issuer's discovery document and keys are saved to whenever they are fetched, so
+/// the server can start while the issuer is unreachable.
+struct Cache {
+    files: Option<(PathBuf, PathBuf)>,
+    log: Logger
+}
+
+impl Cache {
+    fn new(dir: Option<&Path>, issuer: &str, log: Logger) -> Cache {
+        let name = issuer.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        Cache {
+            files: dir.map(|dir| (dir.join(format!("{}.discovery.json", name)),
+                                  dir.join(format!("{}.keys.json", name)))),
+            log
+        }
+    }
+
+    /// The saved discovery document of `issuer`, if it hasn't expired.
+    fn discovery(&self, issuer: &str) -> Option<CachedDiscovery> {
+        self.files.as_ref()
+            .and_then(|&(ref path, _)| self.load(path, CachedDiscovery::from_cache))
+            .filter(|discovery| discovery.issuer() == issuer && !discovery.is_expired())
+    }
+
+    /// The saved keys, if they haven't expired.
+    fn keys(&self) -> Option<CachedKeys> {
+        self.files.as_ref()
+            .and_then(|&(_, ref path)| self.load(path, CachedKeys::from_cache))
+            .filter(|keys| !keys.is_expired())
+    }
+
+    fn save_discovery(&self, discovery: &CachedDiscovery) {
+        if let Some((ref path, _)) = self.files {
+            self.save(path, |file| discovery.save(file));
+        }
+    }
+
+    fn save_keys(&self, keys: &CachedKeys) {
+        if let Some((_, ref path)) = self.files {
+            self.save(path, |file| keys.save(file));
+        }
+    }
+
+    fn load<T, F>(&self, path: &Path, from_cache: F) -> Option<T> where F: FnOnce(File) -> google::error::Result<T> {
+        let file = File::open(path).ok()?;
+        from_cache(file)
+            .map_err(|err| warn!(self.log, "Ignoring unreadable identity provider cache";
+                                 "path" => format!("{}", path.display()), "desc" => format!("{}", err)))
+            .ok()
+    }
+
+    // A failed save only costs a fetch at the next start, so it is logged rather than returned.
+    fn save<F>(&self, path: &Path, save: F) where F: FnOnce(File) -> google::error::Result<()> {
+        // Written beside the cache and renamed over it, so a crash can't leave half a file.
+        let partial = path.with_extension("json.partial");
+        let saved = File::create(&partial)
+            .map_err(|err| google::error::Error::from(google::error::ErrorKind::IoError(err)))
+            .and_then(save)
+            .and_then(|_| fs::rename(&partial, path)
+                .map_err(|err| google::error::Error::from(google::error::ErrorKind::IoError(err))));
+        if let Err(err) = saved {
+            warn!(self.log, "Failed to save identity provider cache";
+                  "path" => format!("{}", path.display()), "desc" => format!("{}", err));
+        }
+    }
+}
+
+/// An identity provider whose ID tokens are trusted, along with its cached discovery document and
+/// signing keys. Tokens are verified against a snapshot of the keys, which are replaced by a
+/// background refresh before they expire.
+pub struct IdentityProvider {
+    verifier: Verifier,
+    subject_prefix: String,
+    keys: RwLock<Arc<CachedKeys>>,
+    discovery: RwLock<Arc<CachedDiscovery>>,
+    last_forced_refresh: Mutex<Option<Instant>>,
+    cache: Cache,
+    client: Arc<Client>
+}
+
+impl IdentityProvider {
+    /// Loads the discovery document and keys of the verifier's issuer from `cache_dir`, fetching
+    /// them if they aren't saved there or have expired. Users signing in through the provider get
+    /// `subject_prefix` in front of their `sub` as their id.
+    pub fn new(client: Arc<Client>, verifier: Verifier, subject_prefix: String, cache_dir: Option<&Path>,
+               log: Logger) -> Result<IdentityProvider> {
+        let cache = Cache::new(cache_dir, verifier.issuer(), log);
+
+        let discovery = match cache.discovery(verifier.issuer()) {
+            Some(discovery) => discovery,
+            None => {
+                let discovery = CachedDiscovery::new(&client, verifier.issuer())
+                    .map_err(|err| Error::from(ErrorKind::GoogleError(err)))?;
+                cache.save_discovery(&discovery);
+                discovery
+            }
+        };
+        let keys = match cache.keys() {
+            Some(keys) => keys,
+            None => {
+                let keys = CachedKeys::new(&client, discovery.current())
+                    .map_err(|err| Error::from(ErrorKind::GoogleError(err)))?;
+                cache.save_keys(&keys);
+                keys
+            }
+        };
+
+        Ok(IdentityProvider {
+            client,
+            verifier,
+            subject_prefix,
+            keys: RwLock::new(Arc::new(keys)),
+            discovery: RwLock::new(Arc::new(discovery)),
+            last_forced_refresh: Mutex::new(None),
+            cache
+        })
+    }
+
+    /// Verifies an ID token against the provider's current keys. A token signed with a key that
+    /// isn't one of them may mean the issuer has rotated its keys, so they are fetched again, at
+    /// most once every `FORCED_REFRESH_SECS`.
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let result = self.verifier.verify(token, self.keys()?.current());
+        let unknown_key = match result {
+            Err(ref err) => match *err.kind() {
+                ErrorKind::NoValidKeyGoogleError => true,
+                _ => false
+            },
+            Ok(_) => false
+        };
+        if !unknown_key {
+            return result
+        }
+
+        self.force_refresh()?;
+        self.verifier.verify(token, self.keys()?.current())
+    }
+
+    /// Refreshes the discovery document and keys if they expire within `REFRESH_AHEAD_SECS`.
+    pub fn refresh_expiring(&self) -> Result<()> {
+        let ahead = chrono::Duration::seconds(REFRESH_AHEAD_SECS);
+        if self.discovery()?.expires_within(ahead) {
+            self.refresh_discovery()?;
+        }
+        if self.keys()?.expires_within(ahead) {
+            self.refresh_keys()?;
+        }
+        Ok(())
+    }
+
+    fn force_refresh(&self) -> Result<()> {
+        // Held while refreshing, so tokens arriving meanwhile wait for the new keys instead of
+        // fetching them again.
+        let mut last = self.last_forced_refresh.lock()
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "last_forced_refresh".to_string())))?;
+        if last.map_or(false, |at| at.elapsed() < Duration::from_secs(FORCED_REFRESH_SECS)) {
+            return Ok(())
+        }
+        *last = Some(Instant::now());
+
+        if let Err(err) = self.refresh_keys() {
+            warn!(self.cache.log, "Failed to refresh keys for an unknown kid"; "desc" => format!("{}", err));
+        }
+        Ok(())
+    }
+
+    // The fetch happens outside the write lock, which is only held to swap in the new snapshot.
+    fn refresh_discovery(&self) -> Result<()> {
+        let discovery = CachedDiscovery::new(&self.client, self.verifier.issuer())
+            .map_err(|err| Error::from(ErrorKind::GoogleError(err)))?;
+        self.cache.save_discovery(&discovery);
+        let mut current = self.discovery.write()
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "CachedDiscovery".to_string())))?;
+        *current = Arc::new(discovery);
+        Ok(())
+    }
+
+    fn refresh_keys(&self) -> Result<()> {
+        let keys = CachedKeys::new(&self.client, self.discovery()?.current())
+            .map_err(|err| Error::from(ErrorKind::GoogleError(err)))?;
+        self.cache.save_keys(&keys);
+        let mut current = self.keys.write()
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "CachedKeys".to_string())))?;
+        *current = Arc::new(keys);
+        Ok(())
+    }
+
+    fn discovery(&self) -> Result<Arc<CachedDiscovery>> {
+        self.discovery.read()
+            .map(|discovery| discovery.clone())
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "CachedDiscovery".to_string())))
+    }
+
+    fn keys(&self) -> Result<Arc<CachedKeys>> {
+        self.keys.read()
+            .map(|keys| keys.clone())
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "CachedKeys".to_string())))
+    }
+}
+
+/// Every trusted identity provider, each verifying the tokens it issued.
+pub struct IdentityProviders {
+    providers: Vec<IdentityProvider>
+}
+
+impl IdentityProviders {
+    pub fn new(providers: Vec<IdentityProvider>) -> IdentityProviders {
+        IdentityProviders {
+            providers
+        }
+    }
+
+    /// The providers in the `identity_providers` array of the config, cached in the `dir` of the
+    /// `identity_cache` table if there is one.
+    pub fn from_config(config: &Config, client: Client, log: &Logger) -> Result<IdentityProviders> {
+        let client = Arc::new(client);
+        let tables = config.get_array("identity_providers")
+            .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?;
+        if tables.is_empty() {
+            bail!(ErrorKind::InvalidConfigTypeError("identity_providers".to_string(),
+                                                    "non-empty array of tables".to_string()))
+        }
+
+        let cache_dir = match config.get_table("identity_cache") {
+            Ok(mut table) => match table.remove("dir") {
+                Some(dir) => Some(dir.into_str()
+                    .map(PathBuf::from)
+                    .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?),
+                None => None
+            },
+            Err(_) => None
+        };
+        if let Some(ref dir) = cache_dir {
+            fs::create_dir_all(dir).map_err(|err| Error::from(ErrorKind::IoError(err)))?;
+        }
+
+        let mut prefixes = Vec::new();
+        tables.into_iter()
+            .map(|table| {
+                let mut table = table.into_table().map_err(|err| Error::from(ErrorKind::ConfigError(err)))?;
+                let prefix = match table.remove("subject_prefix") {
+                    Some(prefix) => prefix.into_str().map_err(|err| Error::from(ErrorKind::ConfigError(err)))?,
+                    None => String::new()
+                };
+                // Subjects are only unique to their issuer, so two providers sharing a prefix could
+                // sign each other's users in.
+                if prefixes.contains(&prefix) {
+                    bail!(ErrorKind::InvalidConfigTypeError("identity_providers.subject_prefix".to_string(),
+                                                            "prefix unique to one provider".to_string()))
+                }
+                prefixes.push(prefix.clone());
+                let verifier = Verifier::from_table(table)?;
+                let log = log.new(o!("issuer" => verifier.issuer().to_string()));
+                IdentityProvider::new(client.clone(), verifier, prefix, cache_dir.as_ref().map(PathBuf::as_path), log)
+            })
+            .collect::<Result<Vec<IdentityProvider>>>()
+            .map(IdentityProviders::new)
+    }
+
+    /// Starts a thread refreshing every provider's discovery document and keys before they expire.
+    pub fn refresh_in_background(providers: &Arc<IdentityProviders>) -> Result<()> {
+        let providers = providers.clone();
+        thread::Builder::new()
+            .name("identity-refresh".to_string())
+            .spawn(move || loop {
+                thread::sleep(Duration::from_secs(REFRESH_CHECK_SECS));
+                for provider in &providers.providers {
+                    if let Err(err) = provider.refresh_expiring() {
+                        // The old keys stay in use, and the refresh is tried again next time round.
+                        warn!(provider.cache.log, "Failed to refresh identity provider"; "desc" => format!("{}", err));
+                    }
+                }
+            })
+            .map(|_| ())
+            .map_err(|err| Error::from(ErrorKind::IoError(err)))
+    }
+
+    /// Verifies an ID token with the provider that issued it. The `sub` of the claims returned is
+    /// prefixed with the provider's subject prefix, making it the id of the user.
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let iss = id_token::unverified_issuer(token)?;
+        let provider = self.providers.iter()
+            .find(|provider| provider.verifier.accepts_issuer(&iss))
+            .ok_or_else(|| Error::from(ErrorKind::InvalidIssuerError(iss)))?;
+
+        provider.verify(token).map(|mut claims| {
+            claims.sub = format!("{}{}", provider.subject_prefix, claims.sub);
+            claims
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::Utc;
+    use jwt::{self, Algorithm, Header};
+    use slog;
+
+    use super::*;
+
+    const CLIENT_ID: &str = "riostu";
+
+    // A local issuer serving its discovery document, then the test key under each of `kids` in
+    // turn, so verification runs end to end. It answers `requests` requests, which it counts, and
+    // then goes away.
+    fn fake_issuer(kids: &'static [&'static str], requests: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let discovery = format!(r#"{{"issuer": "{0}", "authorization_endpoint": "{0}/auth",
+                                    "token_endpoint": "{0}/token", "jwks_uri": "{0}/keys",
+                                    "response_types_supported": ["code"], "subject_types_supported": ["public"],
+                                    "id_token_signing_alg_values_supported": ["RS256"]}}"#, issuer);
+        let served = Arc::new(AtomicUsize::new(0));
+
+        let counter = served.clone();
+        thread::spawn(move || {
+            let mut keys_served = 0;
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+                let request = lines.next().unwrap().unwrap();
+                while lines.next().map_or(false, |line| !line.unwrap().is_empty()) {}
+
+                let body = if request.contains("/.well-known/openid-configuration") {
+                    discovery.clone()
+                } else {
+                    let kid = kids[keys_served.min(kids.len() - 1)];
+                    keys_served += 1;
+                    format!(r#"{{"keys": [{}]}}"#, include_str!("../../testdata/rsa_public.jwk.json")
+                        .replace(r#""kid": "test""#, &format!(r#""kid": "{}""#, kid)))
+                };
+                counter.fetch_add(1, Ordering::SeqCst);
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                                Cache-Control: public, max-age=3600\r\nConnection: close\r\n\r\n{}",
+                       body.len(), body).unwrap();
+            }
+        });
+        (issuer, served)
+    }
+
+    fn provider(issuer: &str, prefix: &str, cache_dir: Option<&Path>) -> Result<IdentityProvider> {
+        let verifier = Verifier::new(vec![issuer.to_string()], vec![CLIENT_ID.to_string()], 60);
+        IdentityProvider::new(Arc::new(Client::new()), verifier, prefix.to_string(), cache_dir,
+                              Logger::root(slog::Discard, o!()))
+    }
+
+    fn token(issuer: &str, kid: &str) -> String {
+        #[derive(Serialize)]
+        struct TestClaims<'a> {
+            iss: &'a str,
+            sub: &'a str,
+            aud: &'a str,
+            email: &'a str,
+            email_verified: bool,
+            iat: i64,
+            exp: i64
+        }
+
+        let now = Utc::now().timestamp();
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        jwt::encode(&header, &TestClaims {
+            iss: issuer,
+            sub: "1234",
+            aud: CLIENT_ID,
+            email: "borrower@example.com",
+            email_verified: true,
+            iat: now,
+            exp: now + 3600
+        }, include_bytes!("../../testdata/rsa_private.der")).unwrap()
+    }
+
+    #[test]
+    fn tokens_are_verified_by_the_provider_that_issued_them() {
+        let ((first, _), (second, _)) = (fake_issuer(&["test"], 2), fake_issuer(&["test"], 2));
+        let providers = IdentityProviders::new(vec![provider(&first, "", None).unwrap(),
+                                                    provider(&second, "keycloak|", None).unwrap()]);
+
+        assert_eq!("1234", providers.verify(&token(&first, "test")).unwrap().sub);
+        assert_eq!("keycloak|1234", providers.verify(&token(&second, "test")).unwrap().sub);
+        assert!(providers.verify(&token("https://evil.example.com", "test")).is_err());
+    }
+
+    #[test]
+    fn starts_from_the_cache_while_the_issuer_is_unreachable() {
+        let (issuer, _) = fake_issuer(&["test"], 2);
+        let dir = env::temp_dir().join(format!("riostu-identity-cache-{}-{}", process::id(),
+                                               issuer.rsplit(':').next().unwrap()));
+        fs::create_dir_all(&dir).unwrap();
+        provider(&issuer, "", Some(&dir)).unwrap();
+
+        // The fake issuer has answered its two requests, so this only works from the cache.
+        let cached = provider(&issuer, "", Some(&dir)).unwrap();
+        assert_eq!("1234", cached.verify(&token(&issuer, "test")).unwrap().sub);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn an_unknown_kid_refreshes_the_keys_at_most_once_a_minute() {
+        let (issuer, served) = fake_issuer(&["old", "test"], 10);
+        let provider = provider(&issuer, "", None).unwrap();
+        assert_eq!(2, served.load(Ordering::SeqCst));
+
+        assert_eq!("1234", provider.verify(&token(&issuer, "test")).unwrap().sub);
+        assert_eq!(3, served.load(Ordering::SeqCst));
+
+        assert!(provider.verify(&token(&issuer, "other")).is_err());
+        assert_eq!(3, served.load(Ordering::SeqCst));
+    }
+}
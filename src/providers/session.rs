@@ -0,0 +1,416 @@
+//! riostu's own session tokens.
+//!
+//! Once a user has signed in with an ID token they are given a short-lived access token, a JWT
+//! signed with the session secret, and a long-lived refresh token. Refresh tokens are random and
+//! only their SHA-256 hash is stored, as a row of the `tokens` table. Refreshing replaces both
+//! tokens, so a refresh token can only be used once. Access tokens only say who the user is, their
+//! roles are looked up on every request so taking one away has effect at once.
+//!
+//! Revoking a session stops its refresh token working at once. Its access token is only checked
+//! against the sessions revoked in the last access token lifetime, which are kept in memory so
+//! verifying one doesn't need the database.
+//!
+//! A session's `last_used_at`, `ip` and `user_agent` are updated as its access tokens are used, but
+//! no more than once a minute unless it moves to another device.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Duration, Utc};
+use config::Config;
+use iron::Request;
+use iron::headers::UserAgent;
+use postgres::GenericConnection;
+use jwt::{self, Algorithm, Header, Validation};
+use rand::{OsRng, Rng};
+use sha2::{Digest, Sha256};
+use base64;
+
+use errors::*;
+use models::{self, Token, User};
+
+/// The `iss` of session access tokens, which tells them apart from ID tokens.
+pub const ISSUER: &str = "riostu";
+/// The environment variable holding the session secret, which takes the place of the one in the
+/// config so it needn't be kept alongside it.
+pub const SECRET_VAR: &str = "RIOSTU_SESSION_SECRET";
+
+const DEFAULT_ACCESS_TTL_SECS: i64 = 15 * 60;
+const DEFAULT_REFRESH_TTL_DAYS: i64 = 30;
+const TOKEN_BYTES: usize = 32;
+const TOUCH_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionClaims {
+    pub iss: String,
+    pub sub: String,
+    /// The `tokens` row of the session.
+    pub sid: i32,
+    pub email: String,
+    pub name: String,
+    pub iat: i64,
+    pub exp: i64
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTokens {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    pub refresh_token: String
+}
+
+/// Where a session is being used from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub ip: String,
+    pub user_agent: Option<String>
+}
+
+impl Device {
+    pub fn from_request(req: &Request) -> Device {
+        Device {
+            ip: req.remote_addr.ip().to_string(),
+            user_agent: req.headers.get::<UserAgent>().map(|agent| agent.to_string())
+        }
+    }
+}
+
+pub struct Sessions {
+    secret: Vec<u8>,
+    access_ttl: Duration,
+    refresh_ttl: Duration,
+    /// Revoked sessions, until their access tokens can no longer be valid.
+    revoked: RwLock<HashMap<i32, DateTime<Utc>>>,
+    /// When each session was last recorded as used, and from where.
+    touched: RwLock<HashMap<i32, (DateTime<Utc>, Device)>>
+}
+
+impl Sessions {
+    pub fn new(secret: Vec<u8>, access_ttl: Duration, refresh_ttl: Duration) -> Sessions {
+        Sessions {
+            secret,
+            access_ttl,
+            refresh_ttl,
+            revoked: RwLock::new(HashMap::new()),
+            touched: RwLock::new(HashMap::new())
+        }
+    }
+
+    /// Sessions signed with the secret in `RIOSTU_SESSION_SECRET`, or if that isn't set the
+    /// `secret` in the `session` table of the config.
+    pub fn from_config(config: &Config) -> Result<Sessions> {
+        let table = config.get_table("session")
+            .map_err(|err| Error::from(ErrorKind::ConfigError(err)))?;
+
+        let configured = match table.get("secret") {
+            Some(v) => Some(v.clone().into_str().map_err(|err| Error::from(ErrorKind::ConfigError(err)))?),
+            None => None
+        };
+        let secret = secret(env::var(SECRET_VAR).ok(), configured)?;
+
+        let access_ttl = match table.get("access_ttl_secs") {
+            Some(v) => v.clone().into_int().map_err(|err| Error::from(ErrorKind::ConfigError(err)))?,
+            None => DEFAULT_ACCESS_TTL_SECS
+        };
+        let refresh_ttl = match table.get("refresh_ttl_days") {
+            Some(v) => v.clone().into_int().map_err(|err| Error::from(ErrorKind::ConfigError(err)))?,
+            None => DEFAULT_REFRESH_TTL_DAYS
+        };
+
+        Ok(Sessions::new(secret.into_bytes(), Duration::seconds(access_ttl), Duration::days(refresh_ttl)))
+    }
+
+    /// Starts a new session for `user` on `device`.
+    pub fn issue<C: GenericConnection>(&self, con: &C, user: &User, device: &Device) -> Result<SessionTokens> {
+
+        let refresh_token = random_token()?;
+        let id: i32 = con.query("INSERT INTO tokens (user_id, token_hash, expires_at, ip, user_agent) \
+                                 VALUES ($1, $2, $3, $4, $5) RETURNING id;",
+                                &[&user.id, &hash(&refresh_token), &(Utc::now() + self.refresh_ttl),
+                                  &device.ip, &device.user_agent])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))?
+            .iter()
+            .next()
+            .map(|row| row.get("id"))
+            .ok_or_else(|| Error::from(ErrorKind::InternalServerError))?;
+        self.tokens(id, user, refresh_token)
+    }
+
+    /// Swaps a refresh token for a new access token and refresh token, used from `device`.
+    pub fn refresh<C: GenericConnection>(&self, con: &C, refresh_token: &str, device: &Device)
+        -> Result<SessionTokens> {
+
+        let next = random_token()?;
+        let (id, user) = con.query("UPDATE tokens SET token_hash = $2, expires_at = $3, refreshed_at = now(), \
+                                    last_used_at = now(), ip = $4, user_agent = $5 \
+                                    FROM users WHERE tokens.token_hash = $1 AND tokens.expires_at > now() \
+                                    AND tokens.revoked_at IS NULL AND users.id = tokens.user_id \
+                                    RETURNING tokens.id, users.id AS user_id, users.name, users.email;",
+                                   &[&hash(refresh_token), &hash(&next), &(Utc::now() + self.refresh_ttl),
+                                     &device.ip, &device.user_agent])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))?
+            .iter()
+            .next()
+            .map(|row| (row.get("id"), User {
+                id: row.get("user_id"),
+                name: row.get("name"),
+                email: row.get("email")
+            }))
+            .ok_or_else(|| Error::from(ErrorKind::InvalidRefreshTokenError))?;
+        self.tokens(id, &user, next)
+    }
+
+    /// Checks the signature and expiry of a session access token, and that its session hasn't
+    /// been revoked.
+    pub fn verify(&self, token: &str) -> Result<SessionClaims> {
+        let mut validation = Validation::default();
+        validation.algorithms = vec![Algorithm::HS256];
+        validation.iss = Some(ISSUER.to_string());
+        validation.leeway = 0;
+        let claims = jwt::decode::<SessionClaims>(token, &self.secret, &validation)
+            .map(|data| data.claims)
+            .map_err(|err| Error::from(ErrorKind::JwtError(err)))?;
+
+        let revoked = self.revoked.read()
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "revoked sessions".to_string())))?;
+        if revoked.contains_key(&claims.sid) {
+            bail!(ErrorKind::RevokedSessionError)
+        }
+        Ok(claims)
+    }
+
+    /// Records that session `id` was used from `device`, unless that was already recorded in the
+    /// last minute.
+    pub fn touch<C: GenericConnection>(&self, con: &C, id: i32, device: &Device) -> Result<()> {
+        let now = Utc::now();
+        if !self.needs_touch(id, device, now)? {
+            return Ok(())
+        }
+        con.execute("UPDATE tokens SET last_used_at = now(), ip = $2, user_agent = $3 WHERE id = $1;",
+                    &[&id, &device.ip, &device.user_agent])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        self.remember_touch(id, device, now)
+    }
+
+    fn needs_touch(&self, id: i32, device: &Device, now: DateTime<Utc>) -> Result<bool> {
+        let touched = self.touched.read()
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "touched sessions".to_string())))?;
+        Ok(match touched.get(&id) {
+            Some(&(at, ref last)) => at + Duration::seconds(TOUCH_INTERVAL_SECS) <= now || last != device,
+            None => true
+        })
+    }
+
+    fn remember_touch(&self, id: i32, device: &Device, now: DateTime<Utc>) -> Result<()> {
+        let mut touched = self.touched.write()
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "touched sessions".to_string())))?;
+        // A session not used for an access token lifetime needs recording again anyway.
+        touched.retain(|_, &mut (at, _)| at + self.access_ttl > now);
+        touched.insert(id, (now, device.clone()));
+        Ok(())
+    }
+
+    /// The sessions of `user_id` that haven't expired or been revoked, most recently used first.
+    pub fn list<C: GenericConnection>(&self, con: &C, user_id: &str) -> Result<Vec<Token>> {
+        con.query(&format!("SELECT {} FROM tokens WHERE user_id = $1 AND revoked_at IS NULL \
+                            AND expires_at > now() ORDER BY last_used_at DESC, id DESC;", models::TOKEN_COLUMNS),
+                  &[&user_id])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)
+            .map(|rows| rows.iter()
+                .map(|row| Token::from_row(&row))
+                .collect())
+    }
+
+    /// Revokes session `id` of `user_id`, returning false if they have no such session.
+    pub fn revoke<C: GenericConnection>(&self, con: &C, user_id: &str, id: i32) -> Result<bool> {
+        let revoked = con.execute("UPDATE tokens SET revoked_at = now() WHERE id = $1 AND user_id = $2 \
+                                   AND revoked_at IS NULL AND expires_at > now();", &[&id, &user_id])
+            .map(|updated| updated > 0)
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        if revoked {
+            self.remember_revoked(id, Utc::now())?;
+        }
+        Ok(revoked)
+    }
+
+    /// Remembers the sessions revoked recently enough for their access tokens to still be valid,
+    /// so revocations survive a restart.
+    pub fn load_revoked<C: GenericConnection>(&self, con: &C) -> Result<()> {
+        let rows = con.query("SELECT id, revoked_at FROM tokens WHERE revoked_at > $1;",
+                             &[&(Utc::now() - self.access_ttl)])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))?;
+        for row in rows.iter() {
+            self.remember_revoked(row.get("id"), row.get("revoked_at"))?;
+        }
+        Ok(())
+    }
+
+    fn remember_revoked(&self, id: i32, revoked_at: DateTime<Utc>) -> Result<()> {
+        let now = Utc::now();
+        let mut revoked = self.revoked.write()
+            .map_err(|err| Error::from(ErrorKind::PoisonError(format!("{}", err), "revoked sessions".to_string())))?;
+        // Every access token of a session revoked longer ago than that has expired.
+        revoked.retain(|_, until| *until > now);
+        revoked.insert(id, revoked_at + self.access_ttl);
+        Ok(())
+    }
+
+    fn tokens(&self, id: i32, user: &User, refresh_token: String) -> Result<SessionTokens> {
+        let now = Utc::now();
+        let claims = SessionClaims {
+            iss: ISSUER.to_string(),
+            sub: user.id.clone(),
+            sid: id,
+            email: user.email.clone(),
+            name: user.name.clone(),
+            iat: now.timestamp(),
+            exp: (now + self.access_ttl).timestamp()
+        };
+        let access_token = jwt::encode(&Header::new(Algorithm::HS256), &claims, &self.secret)
+            .map_err(|err| Error::from(ErrorKind::JwtError(err)))?;
+
+        Ok(SessionTokens {
+            access_token,
+            token_type: "Bearer",
+            expires_in: self.access_ttl.num_seconds(),
+            refresh_token
+        })
+    }
+}
+
+/// 256 random bits from the OS, base64url encoded.
+pub fn random_token() -> Result<String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng::new()
+        .map_err(|err| Error::from(ErrorKind::IoError(err)))?
+        .fill_bytes(&mut bytes);
+    Ok(base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD))
+}
+
+/// The hex encoded SHA-256 hash stored in place of a token.
+pub fn hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The session secret, from the environment if it is set there and the config if not.
+fn secret(from_env: Option<String>, from_config: Option<String>) -> Result<String> {
+    from_env.into_iter()
+        .chain(from_config)
+        .find(|secret| !secret.is_empty())
+        .ok_or_else(|| Error::from(ErrorKind::MissingConfigValueTableError("secret".to_string(),
+                                                                           "session".to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions() -> Sessions {
+        Sessions::new(b"secret".to_vec(), Duration::minutes(15), Duration::days(30))
+    }
+
+    fn user() -> User {
+        User {
+            id: "110169484474386276334".to_string(),
+            name: "Borrower".to_string(),
+            email: "borrower@example.com".to_string()
+        }
+    }
+
+    #[test]
+    fn access_tokens_verify_with_the_same_secret() {
+        let tokens = sessions().tokens(7, &user(), "refresh".to_string()).unwrap();
+        let claims = sessions().verify(&tokens.access_token).unwrap();
+        assert_eq!("110169484474386276334", claims.sub);
+        assert_eq!(7, claims.sid);
+        assert_eq!(900, tokens.expires_in);
+
+        let other = Sessions::new(b"other".to_vec(), Duration::minutes(15), Duration::days(30));
+        assert!(other.verify(&tokens.access_token).is_err());
+    }
+
+    #[test]
+    fn expired_access_tokens_are_rejected() {
+        let expired = Sessions::new(b"secret".to_vec(), Duration::minutes(-1), Duration::days(30));
+        let tokens = expired.tokens(7, &user(), "refresh".to_string()).unwrap();
+        assert!(sessions().verify(&tokens.access_token).is_err());
+    }
+
+    #[test]
+    fn revoked_sessions_are_rejected_until_their_access_tokens_expire() {
+        let sessions = sessions();
+        let tokens = sessions.tokens(7, &user(), "refresh".to_string()).unwrap();
+        let other = sessions.tokens(8, &user(), "refresh".to_string()).unwrap();
+
+        sessions.remember_revoked(7, Utc::now()).unwrap();
+        assert!(sessions.verify(&tokens.access_token).is_err());
+        assert!(sessions.verify(&other.access_token).is_ok());
+
+        sessions.remember_revoked(8, Utc::now() - Duration::minutes(20)).unwrap();
+        sessions.remember_revoked(9, Utc::now()).unwrap();
+        assert_eq!(2, sessions.revoked.read().unwrap().len());
+    }
+
+    #[test]
+    fn refresh_tokens_are_random_and_stored_hashed() {
+        let token = random_token().unwrap();
+        assert_ne!(token, random_token().unwrap());
+        assert_eq!(43, token.len());
+        assert_eq!("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824", hash("hello"));
+    }
+
+    #[test]
+    fn the_environment_secret_comes_first() {
+        let secret = |from_env: Option<&str>, from_config: Option<&str>|
+            super::secret(from_env.map(String::from), from_config.map(String::from)).ok();
+        assert_eq!(Some("env".to_string()), secret(Some("env"), Some("config")));
+        assert_eq!(Some("config".to_string()), secret(None, Some("config")));
+        assert_eq!(Some("config".to_string()), secret(Some(""), Some("config")));
+        assert_eq!(None, secret(None, Some("")));
+        assert_eq!(None, secret(None, None));
+    }
+
+    #[test]
+    fn touches_are_throttled_unless_the_device_changes() {
+        let sessions = sessions();
+        let now = Utc::now();
+        let laptop = Device { ip: "10.0.0.1".to_string(), user_agent: Some("Firefox".to_string()) };
+        let phone = Device { ip: "10.0.0.2".to_string(), user_agent: None };
+
+        let touch = |id, device, secs| {
+            let at = now + Duration::seconds(secs);
+            let needed = sessions.needs_touch(id, device, at).unwrap();
+            if needed {
+                sessions.remember_touch(id, device, at).unwrap();
+            }
+            needed
+        };
+
+        assert!(touch(7, &laptop, 0));
+        assert!(!touch(7, &laptop, 30));
+        assert!(touch(8, &laptop, 30));
+        assert!(touch(7, &phone, 40));
+        assert!(!touch(7, &phone, 90));
+        assert!(touch(7, &phone, 100));
+    }
+
+    #[test]
+    fn touches_are_only_remembered_once_recorded() {
+        let sessions = sessions();
+        let now = Utc::now();
+        let laptop = Device { ip: "10.0.0.1".to_string(), user_agent: None };
+
+        // A failed write never gets as far as remember_touch, so the next use tries again.
+        assert!(sessions.needs_touch(7, &laptop, now).unwrap());
+        assert!(sessions.needs_touch(7, &laptop, now + Duration::seconds(1)).unwrap());
+        sessions.remember_touch(7, &laptop, now + Duration::seconds(1)).unwrap();
+        assert!(!sessions.needs_touch(7, &laptop, now + Duration::seconds(2)).unwrap());
+    }
+}
@@ -0,0 +1,107 @@
+//! Who may sign up.
+//!
+//! Users are created the first time they sign in, from the claims of their verified ID token, and
+//! their name and email are refreshed on every later sign in. New users can be limited to email
+//! addresses in some domains, or to a list of addresses. With neither anyone may sign up.
+
+use config::{Config, Value};
+use postgres::GenericConnection;
+
+use errors::*;
+use models::{self, User};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Registration {
+    domains: Vec<String>,
+    allow: Vec<String>
+}
+
+impl Registration {
+    pub fn new(domains: Vec<String>, allow: Vec<String>) -> Registration {
+        Registration {
+            domains: domains.into_iter().map(|domain| domain.to_lowercase()).collect(),
+            allow: allow.into_iter().map(|email| email.to_lowercase()).collect()
+        }
+    }
+
+    /// The rules in the `registration` table of the config, or open registration if there isn't one.
+    pub fn from_config(config: &Config) -> Result<Registration> {
+        let mut table = match config.get_table("registration") {
+            Ok(table) => table,
+            Err(_) => return Ok(Registration::default())
+        };
+
+        let domains = strings(table.remove("domains"))?;
+        let allow = strings(table.remove("allow"))?;
+        Ok(Registration::new(domains, allow))
+    }
+
+    /// Whether someone with the verified `email` may sign up.
+    pub fn permits(&self, email: &str) -> bool {
+        if self.domains.is_empty() && self.allow.is_empty() {
+            return true
+        }
+
+        let email = email.to_lowercase();
+        let domain = email.rsplitn(2, '@').next().unwrap_or("");
+        self.allow.contains(&email) || (email.contains('@') && self.domains.iter().any(|allowed| allowed == domain))
+    }
+}
+
+fn strings(value: Option<Value>) -> Result<Vec<String>> {
+    match value {
+        Some(value) => value.into_array()
+            .and_then(|values| values.into_iter().map(|value| value.into_str()).collect())
+            .map_err(|err| Error::from(ErrorKind::ConfigError(err))),
+        None => Ok(Vec::new())
+    }
+}
+
+/// Creates or updates the row of `user`, who has just signed in. The `admin` from the config may
+/// always sign up, so the platform can't be locked out of.
+pub fn provision<C: GenericConnection>(con: &C, registration: &Registration, admin: Option<&str>, user: &User)
+    -> Result<User> {
+    let updated = con.query(&format!("UPDATE users SET name = $2, email = $3 WHERE id = $1 RETURNING {};",
+                                     models::USER_COLUMNS), &[&user.id, &user.name, &user.email])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))?
+        .iter()
+        .next()
+        .map(|row| User::from_row(&row));
+    if let Some(updated) = updated {
+        return Ok(updated)
+    }
+
+    if admin != Some(user.id.as_str()) && !registration.permits(&user.email) {
+        bail!(ErrorKind::RegistrationClosedError(user.email.clone()))
+    }
+    // Someone signing in twice at once may have been created since the update.
+    con.query(&format!("INSERT INTO users (id, name, email) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE \
+                        SET name = EXCLUDED.name, email = EXCLUDED.email RETURNING {};", models::USER_COLUMNS),
+              &[&user.id, &user.name, &user.email])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))?
+        .iter()
+        .next()
+        .map(|row| User::from_row(&row))
+        .ok_or_else(|| Error::from(ErrorKind::InternalServerError))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anyone_may_sign_up_without_rules() {
+        assert!(Registration::default().permits("anyone@example.com"));
+    }
+
+    #[test]
+    fn domains_and_addresses_limit_sign_up() {
+        let registration = Registration::new(vec!["Reilly-Family.co.uk".to_string()],
+                                             vec!["friend@example.com".to_string()]);
+        assert!(registration.permits("stu@reilly-family.co.uk"));
+        assert!(registration.permits("Friend@Example.com"));
+        assert!(!registration.permits("stranger@example.com"));
+        assert!(!registration.permits("stu@evil-reilly-family.co.uk"));
+        assert!(!registration.permits("reilly-family.co.uk"));
+    }
+}
@@ -0,0 +1,51 @@
+use iron::prelude::*;
+use iron::status;
+use postgres::Connection;
+
+use errors::*;
+use models;
+use json;
+use interest::Balance;
+use super::repayment;
+
+#[derive(Debug, Serialize)]
+struct RequestDetail {
+    request: models::Request,
+    balance: Balance,
+    events: Vec<models::RequestEvent>
+}
+
+pub fn find(con: &Connection, id: i32) -> Result<models::Request> {
+    con.query(&format!("SELECT {} FROM requests WHERE id = $1;", models::REQUEST_COLUMNS), &[&id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?
+        .iter()
+        .next()
+        .ok_or_else(|| Error::from(ErrorKind::NotFoundError(format!("request {}", id))))
+        .and_then(|row| models::Request::from_row(&row))
+}
+
+pub fn events(con: &Connection, id: i32) -> Result<Vec<models::RequestEvent>> {
+    con.query("SELECT id, request_id, kind, actor, amount, created_at FROM request_events \
+               WHERE request_id = $1 ORDER BY created_at, id;", &[&id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+        .map(|rows| rows.iter()
+            .map(|row| models::RequestEvent::from_row(&row))
+            .collect())
+}
+
+pub fn detail(con: &Connection, caller: &str, view_all: bool, id: i32) -> Result<Response> {
+    let request = find(con, id)?;
+    if !view_all && request.user_id != caller {
+        bail!(ErrorKind::ForbiddenError)
+    }
+
+    let events = events(con, id)?;
+    let repayments = repayment::repayments(con, id)?;
+    json::response(status::Ok, &RequestDetail {
+        balance: request.balance(&repayments),
+        request,
+        events
+    })
+}
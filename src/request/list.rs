@@ -0,0 +1,319 @@
+use std::str::FromStr;
+
+use iron::prelude::*;
+use iron::status;
+use urlencoded::QueryMap;
+use postgres::Connection;
+use postgres::types::ToSql;
+use chrono::{DateTime, Utc};
+use base64;
+use serde_json;
+
+use errors::*;
+use models;
+use json;
+use lifecycle::Status;
+use interest::Balance;
+use money::Money;
+use params::single_value;
+use super::repayment;
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    CreatedAt,
+    Amount
+}
+
+impl SortKey {
+    fn column(&self) -> &'static str {
+        match *self {
+            SortKey::CreatedAt => "created_at",
+            SortKey::Amount => "amount"
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SortKey> {
+        match s {
+            "created_at" => Ok(SortKey::CreatedAt),
+            "amount" => Ok(SortKey::Amount),
+            _ => Err(Error::from(ErrorKind::InvalidRequestDataError("sort".to_string())))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Order {
+    Asc,
+    Desc
+}
+
+impl Order {
+    fn keyword(&self) -> &'static str {
+        match *self {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC"
+        }
+    }
+
+    fn after(&self) -> &'static str {
+        match *self {
+            Order::Asc => ">",
+            Order::Desc => "<"
+        }
+    }
+}
+
+impl FromStr for Order {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Order> {
+        match s {
+            "asc" => Ok(Order::Asc),
+            "desc" => Ok(Order::Desc),
+            _ => Err(Error::from(ErrorKind::InvalidRequestDataError("order".to_string())))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Cursor {
+    id: i32,
+    amount: Money,
+    created_at: DateTime<Utc>
+}
+
+impl Cursor {
+    fn encode(&self) -> Result<String> {
+        serde_json::to_vec(self)
+            .map(|json| base64::encode_config(&json, base64::URL_SAFE))
+            .map_err(|err| Error::from(ErrorKind::JsonError(err)))
+    }
+
+    fn decode(s: &str) -> Result<Cursor> {
+        base64::decode_config(s, base64::URL_SAFE)
+            .map_err(|err| Error::from(ErrorKind::Base64Error(err)))
+            .and_then(|json| serde_json::from_slice(&json)
+                .map_err(|err| Error::from(ErrorKind::JsonError(err))))
+            .chain_err(|| ErrorKind::InvalidRequestDataError("cursor".to_string()))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Listed {
+    request: models::Request,
+    balance: Balance
+}
+
+#[derive(Debug, Serialize)]
+struct Page {
+    requests: Vec<Listed>,
+    next_cursor: Option<String>
+}
+
+struct ListQuery {
+    user: Option<String>,
+    status: Option<Status>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    sort: SortKey,
+    order: Order,
+    limit: i64,
+    cursor: Option<Cursor>
+}
+
+impl ListQuery {
+    fn from_query(map: &mut QueryMap) -> Result<ListQuery> {
+        Ok(ListQuery {
+            user: single_value(map, "user")?,
+            status: match single_value(map, "status")? {
+                Some(status) => Some(status.parse()?),
+                None => None
+            },
+            from: match single_value(map, "from")? {
+                Some(from) => Some(parse_date("from", &from)?),
+                None => None
+            },
+            to: match single_value(map, "to")? {
+                Some(to) => Some(parse_date("to", &to)?),
+                None => None
+            },
+            sort: single_value(map, "sort")?.map_or(Ok(SortKey::CreatedAt), |sort| sort.parse())?,
+            order: single_value(map, "order")?.map_or(Ok(Order::Desc), |order| order.parse())?,
+            limit: single_value(map, "limit")?
+                .map_or(Ok(DEFAULT_LIMIT), |limit| limit.parse::<i64>()
+                    .map_err(|_| Error::from(ErrorKind::InvalidRequestDataError("limit".to_string()))))
+                .map(|limit| limit.max(1).min(MAX_LIMIT))?,
+            cursor: match single_value(map, "cursor")? {
+                Some(cursor) => Some(Cursor::decode(&cursor)?),
+                None => None
+            }
+        })
+    }
+
+    /// Limits the query to the requests of `caller` unless they can see everyone's. Asking for
+    /// another borrower's requests without that is forbidden rather than quietly ignored.
+    fn scope(&mut self, caller: &str, view_all: bool) -> Result<()> {
+        if view_all {
+            return Ok(())
+        }
+        match self.user {
+            Some(ref user) if user != caller => bail!(ErrorKind::ForbiddenError),
+            _ => {}
+        }
+        self.user = Some(caller.to_string());
+        Ok(())
+    }
+
+    fn run(&self, con: &Connection) -> Result<Page> {
+        let fetch = self.limit + 1;
+        let status = self.status.map(|status| status.to_string());
+        let mut clauses = Vec::new();
+        let mut params: Vec<&ToSql> = Vec::new();
+
+        if let Some(ref user) = self.user {
+            params.push(user);
+            clauses.push(format!("user_id = ${}", params.len()));
+        }
+        if let Some(ref status) = status {
+            params.push(status);
+            clauses.push(format!("status = ${}", params.len()));
+        }
+        if let Some(ref from) = self.from {
+            params.push(from);
+            clauses.push(format!("created_at >= ${}", params.len()));
+        }
+        if let Some(ref to) = self.to {
+            params.push(to);
+            clauses.push(format!("created_at < ${}", params.len()));
+        }
+        if let Some(ref cursor) = self.cursor {
+            match self.sort {
+                SortKey::CreatedAt => params.push(&cursor.created_at),
+                SortKey::Amount => params.push(&cursor.amount)
+            }
+            params.push(&cursor.id);
+            clauses.push(format!("({}, id) {} (${}, ${})", self.sort.column(), self.order.after(),
+                                 params.len() - 1, params.len()));
+        }
+        params.push(&fetch);
+
+        let filter = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {} ", clauses.join(" AND "))
+        };
+        let sql = format!("SELECT {} FROM requests {}ORDER BY {} {}, id {} LIMIT ${};",
+                          models::REQUEST_COLUMNS, filter, self.sort.column(), self.order.keyword(), self.order.keyword(), params.len());
+
+        let mut requests = con.query(&sql, &params)
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))?
+            .iter()
+            .map(|row| models::Request::from_row(&row))
+            .collect::<Result<Vec<models::Request>>>()?;
+
+        let next_cursor = if requests.len() as i64 > self.limit {
+            requests.truncate(self.limit as usize);
+            match requests.last() {
+                Some(last) => Some(Cursor {
+                    id: last.id,
+                    amount: last.amount,
+                    created_at: last.created_at
+                }.encode()?),
+                None => None
+            }
+        } else {
+            None
+        };
+
+        let ids = requests.iter().map(|request| request.id).collect::<Vec<i32>>();
+        let repayments = repayment::by_request(con, &ids)?;
+
+        Ok(Page {
+            requests: requests.into_iter()
+                .map(|request| Listed {
+                    balance: request.balance(repayments.get(&request.id).map(Vec::as_slice).unwrap_or(&[])),
+                    request
+                }).collect(),
+            next_cursor
+        })
+    }
+}
+
+pub fn list(con: &Connection, caller: &str, view_all: bool, map: &mut QueryMap) -> Result<Response> {
+    let mut query = ListQuery::from_query(map).chain_err(|| ErrorKind::BadRequestError)?;
+    query.scope(caller, view_all)?;
+
+    query.run(con)
+        .chain_err(|| ErrorKind::InternalServerError)
+        .and_then(|page| json::response(status::Ok, &page))
+}
+
+fn parse_date(name: &str, s: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|_| Error::from(ErrorKind::InvalidRequestDataError(name.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> Result<ListQuery> {
+        let mut map = QueryMap::new();
+        for &(key, value) in pairs {
+            map.entry(key.to_string()).or_insert_with(Vec::new).push(value.to_string());
+        }
+        ListQuery::from_query(&mut map)
+    }
+
+    #[test]
+    fn borrowers_only_see_their_own_requests() {
+        let mut own = query(&[]).unwrap();
+        own.scope("alice", false).unwrap();
+        assert_eq!(Some("alice".to_string()), own.user);
+
+        let mut other = query(&[("user", "bob")]).unwrap();
+        match *other.scope("alice", false).unwrap_err().kind() {
+            ErrorKind::ForbiddenError => {},
+            ref kind => panic!("Expected forbidden, got {:?}", kind)
+        }
+
+        let mut all = query(&[]).unwrap();
+        all.scope("admin", true).unwrap();
+        assert_eq!(None, all.user);
+    }
+
+    #[test]
+    fn parses_filters_and_clamps_the_limit() {
+        let parsed = query(&[("status", "pending"), ("sort", "amount"), ("order", "asc"), ("limit", "1000")]).unwrap();
+        assert_eq!(Some(Status::Pending), parsed.status);
+        assert_eq!(SortKey::Amount, parsed.sort);
+        assert_eq!(Order::Asc, parsed.order);
+        assert_eq!(MAX_LIMIT, parsed.limit);
+
+        assert!(query(&[("sort", "user_id")]).is_err());
+        assert!(query(&[("limit", "ten")]).is_err());
+        assert!(query(&[("status", "pending"), ("status", "repaid")]).is_err());
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = Cursor {
+            id: 12,
+            amount: Money::from_minor_units(1234),
+            created_at: Utc::now()
+        };
+        let decoded = Cursor::decode(&cursor.encode().unwrap()).unwrap();
+        assert_eq!(cursor.id, decoded.id);
+        assert_eq!(cursor.amount, decoded.amount);
+        assert_eq!(cursor.created_at, decoded.created_at);
+        assert!(Cursor::decode("not a cursor").is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use iron::prelude::*;
+use iron::Handler;
+use iron::method::Method;
+use iron::status;
+use urlencoded::QueryResult;
+
+use errors::*;
+use providers::Database;
+use models::{self, NewRequest, NewRepayment};
+use json;
+use params::{self, body, query_map, caller, required_value, single_value};
+use lifecycle::Action;
+use interest::Terms;
+use currency::Currency;
+use policy::{self, Limits};
+use roles::Role;
+
+mod list;
+mod detail;
+mod transition;
+pub mod repayment;
+mod schedule;
+
+pub struct RequestHandler {
+    terms: Terms,
+    home: Currency,
+    limits: Limits
+}
+
+impl NewRequest {
+    fn from_query(map_res: QueryResult) -> Result<NewRequest> {
+        let mut map = map_res.map_err(|err| Error::from(ErrorKind::RequestDecodeError(err)))
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let amount = required_value(&mut map, "amount")
+            .and_then(|amount| amount.parse())
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let currency = match single_value(&mut map, "currency").chain_err(|| ErrorKind::BadRequestError)? {
+            Some(currency) => Some(currency.parse().chain_err(|| ErrorKind::BadRequestError)?),
+            None => None
+        };
+
+        let due_date = match single_value(&mut map, "due_date").chain_err(|| ErrorKind::BadRequestError)? {
+            Some(due_date) => Some(params::date("due_date", &due_date).chain_err(|| ErrorKind::BadRequestError)?),
+            None => None
+        };
+
+        let instalments = match single_value(&mut map, "instalments").chain_err(|| ErrorKind::BadRequestError)? {
+            Some(instalments) => Some(instalments.parse::<i32>()
+                .map_err(|_| Error::from(ErrorKind::InvalidRequestDataError("instalments".to_string())))
+                .chain_err(|| ErrorKind::BadRequestError)?),
+            None => None
+        };
+
+        let frequency = match single_value(&mut map, "frequency").chain_err(|| ErrorKind::BadRequestError)? {
+            Some(frequency) => Some(frequency.parse().chain_err(|| ErrorKind::BadRequestError)?),
+            None => None
+        };
+
+        let first_due = match single_value(&mut map, "first_due").chain_err(|| ErrorKind::BadRequestError)? {
+            Some(first_due) => Some(params::date("first_due", &first_due).chain_err(|| ErrorKind::BadRequestError)?),
+            None => None
+        };
+
+        Ok(NewRequest{
+            amount,
+            currency,
+            due_date,
+            instalments,
+            frequency,
+            first_due
+        })
+    }
+
+}
+
+impl RequestHandler {
+    pub fn new(terms: Terms, home: Currency, limits: Limits) -> RequestHandler {
+        RequestHandler {
+            terms,
+            home,
+            limits
+        }
+    }
+
+    fn create(&self, req: &mut Request) -> Result<Response> {
+        // Requests are always filed by the signed in user, never for someone else.
+        let caller = caller(req)?;
+        if !caller.has_role(Role::Borrower) {
+            bail!(ErrorKind::ForbiddenError)
+        }
+        let user_id = caller.subject;
+        let request = body(req, NewRequest::from_query)?;
+        let currency = request.currency.unwrap_or(self.home);
+        let amount = request.amount.in_currency(currency)?;
+        let plan = request.plan(amount)?;
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let trans = con.transaction()
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+
+        // Lock the borrower so concurrent requests are checked one after the other.
+        trans.execute("SELECT id FROM users WHERE id = $1 FOR UPDATE;", &[&user_id])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        policy::check(&trans, &self.limits, self.home, &user_id, amount, currency)?;
+
+        let created = trans.query(&format!("INSERT INTO requests (user_id, amount, currency, interest_method, \
+                                            interest_rate_bps) VALUES ($1, $2, $3, $4, $5) RETURNING {};",
+                                           models::REQUEST_COLUMNS),
+                                  &[&user_id, &amount, &currency,
+                                    &self.terms.method.as_ref(), &self.terms.annual_rate_bps])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .and_then(|rows| rows.iter().next()
+                .ok_or_else(|| Error::from(ErrorKind::InternalServerError))
+                .and_then(|row| models::Request::from_row(&row)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+
+        if let Some(plan) = plan {
+            schedule::create(&trans, &created, &plan)?;
+        }
+
+        trans.execute("INSERT INTO request_events (request_id, kind, actor, amount) VALUES ($1, 'created', $2, $3);",
+                      &[&created.id, &created.user_id, &created.amount])
+            .and_then(|_| trans.commit())
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+
+        json::response(status::Created, &created)
+    }
+
+    fn list(&self, req: &mut Request) -> Result<Response> {
+        let caller = caller(req)?;
+        let mut map = query_map(req)?;
+        Database::connection(req)
+            .chain_err(|| ErrorKind::InternalServerError)
+            .and_then(|con| list::list(&con, &caller.subject, caller.can_view_all(), &mut map))
+    }
+
+    fn detail(&self, req: &mut Request, id: i32) -> Result<Response> {
+        let caller = caller(req)?;
+        Database::connection(req)
+            .chain_err(|| ErrorKind::InternalServerError)
+            .and_then(|con| detail::detail(&con, &caller.subject, caller.can_view_all(), id))
+    }
+
+    fn transition(&self, req: &mut Request, id: i32, action: Action) -> Result<Response> {
+        let caller = caller(req)?;
+        Database::connection(req)
+            .chain_err(|| ErrorKind::InternalServerError)
+            .and_then(|con| transition::transition(&con, &caller.subject, caller.is_admin(), id, action))
+    }
+
+    fn repayments(&self, req: &mut Request, id: i32) -> Result<Response> {
+        let caller = caller(req)?;
+        Database::connection(req)
+            .chain_err(|| ErrorKind::InternalServerError)
+            .and_then(|con| repayment::list(&con, &caller.subject, caller.can_view_all(), id))
+    }
+
+    fn schedule(&self, req: &mut Request, id: i32) -> Result<Response> {
+        let caller = caller(req)?;
+        Database::connection(req)
+            .chain_err(|| ErrorKind::InternalServerError)
+            .and_then(|con| schedule::schedule(&con, &caller.subject, caller.can_view_all(), id))
+    }
+
+    fn repay(&self, req: &mut Request, id: i32) -> Result<Response> {
+        let caller = caller(req)?;
+        let repayment = body(req, NewRepayment::from_query)?;
+        Database::connection(req)
+            .chain_err(|| ErrorKind::InternalServerError)
+            .and_then(|con| repayment::record(&con, &caller.subject, caller.is_admin(), id, repayment))
+    }
+}
+
+impl Handler for RequestHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = params::path(req);
+
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Post, 0) => self.create(req),
+            (Method::Get, 0) => self.list(req),
+            (Method::Get, 1) => params::id("request", &path[0]).and_then(|id| self.detail(req, id)),
+            (Method::Get, 2) if path[1] == "repayments" => params::id("request", &path[0])
+                .and_then(|id| self.repayments(req, id)),
+            (Method::Get, 2) if path[1] == "schedule" => params::id("request", &path[0])
+                .and_then(|id| self.schedule(req, id)),
+            (Method::Post, 2) if path[1] == "repayments" => params::id("request", &path[0])
+                .and_then(|id| self.repay(req, id)),
+            (Method::Post, 2) => params::id("request", &path[0])
+                .and_then(|id| path[1].parse::<Action>().and_then(|action| self.transition(req, id, action))),
+            (_, 0) | (_, 1) | (_, 2) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support GET and POST!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use urlencoded::QueryMap;
+
+    use super::*;
+    use money::Money;
+
+    fn query(pairs: &[(&str, &str)]) -> QueryResult {
+        let mut map = QueryMap::new();
+        for &(key, value) in pairs {
+            map.entry(key.to_string()).or_insert_with(Vec::new).push(value.to_string());
+        }
+        Ok(map)
+    }
+
+    #[test]
+    fn new_requests_parse_from_forms() {
+        let request = NewRequest::from_query(query(&[("amount", "€12.50"), ("currency", "eur"),
+                                                     ("instalments", "3")])).unwrap();
+        let euros = "EUR".parse::<Currency>().unwrap();
+        assert_eq!(Some(euros), request.currency);
+        assert_eq!(Money::from_minor_units(1250), request.amount.in_currency(euros).unwrap());
+        assert!(request.amount.in_currency(Currency::default()).is_err());
+        assert_eq!(Some(3), request.instalments);
+        assert_eq!(None, request.due_date);
+    }
+
+    #[test]
+    fn new_requests_need_a_valid_amount() {
+        assert!(NewRequest::from_query(query(&[])).is_err());
+        assert!(NewRequest::from_query(query(&[("amount", "-5")])).is_err());
+        assert!(NewRequest::from_query(query(&[("amount", "5"), ("amount", "6")])).is_err());
+        assert!(NewRequest::from_query(query(&[("amount", "5"), ("instalments", "three")])).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use iron::prelude::*;
+use iron::status;
+use postgres::{Connection, GenericConnection};
+use urlencoded::QueryResult;
+use chrono::Utc;
+
+use errors::*;
+use models::{self, Repayment, NewRepayment};
+use json;
+use lifecycle::Status;
+use interest::Balance;
+use money::Money;
+use params::{required_value, single_value};
+use super::{detail, schedule};
+
+#[derive(Debug, Serialize)]
+struct Repayments {
+    repayments: Vec<Repayment>,
+    balance: Balance
+}
+
+#[derive(Debug, Serialize)]
+struct Receipt {
+    repayment: Repayment,
+    request: models::Request,
+    balance: Balance
+}
+
+impl NewRepayment {
+    pub fn from_query(map_res: QueryResult) -> Result<NewRepayment> {
+        let mut map = map_res.map_err(|err| Error::from(ErrorKind::RequestDecodeError(err)))
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let amount = required_value(&mut map, "amount")
+            .and_then(|amount| amount.parse())
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let currency = match single_value(&mut map, "currency").chain_err(|| ErrorKind::BadRequestError)? {
+            Some(currency) => Some(currency.parse().chain_err(|| ErrorKind::BadRequestError)?),
+            None => None
+        };
+
+        Ok(NewRepayment {
+            amount,
+            currency
+        })
+    }
+}
+
+/// Whether paying `amount` off a request owing `owed` repays it in full. Paying more than is owed
+/// is refused.
+fn settles(amount: Money, owed: Money) -> Result<bool> {
+    if amount > owed {
+        bail!(ErrorKind::OverpaymentError(amount, owed))
+    }
+    Ok(amount == owed)
+}
+
+pub fn repayments<C: GenericConnection>(con: &C, id: i32) -> Result<Vec<Repayment>> {
+    con.query(&format!("SELECT {} FROM repayments WHERE request_id = $1 ORDER BY paid_at, id;",
+                       models::REPAYMENT_COLUMNS), &[&id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+        .map(|rows| rows.iter()
+            .map(|row| Repayment::from_row(&row))
+            .collect())
+}
+
+/// The repayments of each of the requests in `ids`, in the order they were paid.
+pub fn by_request<C: GenericConnection>(con: &C, ids: &[i32]) -> Result<HashMap<i32, Vec<Repayment>>> {
+    let mut repayments = HashMap::new();
+    for row in con.query(&format!("SELECT {} FROM repayments WHERE request_id = ANY($1) ORDER BY paid_at, id;",
+                                  models::REPAYMENT_COLUMNS), &[&ids])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?
+        .iter() {
+        let repayment = Repayment::from_row(&row);
+        repayments.entry(repayment.request_id).or_insert_with(Vec::new).push(repayment);
+    }
+    Ok(repayments)
+}
+
+pub fn list(con: &Connection, caller: &str, view_all: bool, id: i32) -> Result<Response> {
+    let request = detail::find(con, id)?;
+    if !view_all && request.user_id != caller {
+        bail!(ErrorKind::ForbiddenError)
+    }
+
+    let repayments = repayments(con, id)?;
+    json::response(status::Ok, &Repayments {
+        balance: request.balance(&repayments),
+        repayments
+    })
+}
+
+pub fn record(con: &Connection, caller: &str, is_admin: bool, id: i32, new: NewRepayment) -> Result<Response> {
+    let trans = con.transaction()
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?;
+
+    // Lock the request so concurrent repayments can't both pass the overpayment check.
+    let request = trans.query(&format!("SELECT {} FROM requests WHERE id = $1 FOR UPDATE;", models::REQUEST_COLUMNS),
+                              &[&id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?
+        .iter()
+        .next()
+        .ok_or_else(|| Error::from(ErrorKind::NotFoundError(format!("request {}", id))))
+        .and_then(|row| models::Request::from_row(&row))?;
+
+    if !is_admin && request.user_id != caller {
+        bail!(ErrorKind::ForbiddenError)
+    }
+    if request.status != Status::Disbursed {
+        bail!(ErrorKind::NotRepayableError(request.status.to_string()))
+    }
+    if let Some(currency) = new.currency {
+        if currency != request.currency {
+            bail!(ErrorKind::CurrencyMismatchError(currency.to_string(), request.currency.to_string()))
+        }
+    }
+
+    let mut paid = repayments(&trans, id)?;
+    let amount = new.amount.in_currency(request.currency)?;
+    let repaid_in_full = settles(amount, request.balance(&paid).total_owed)?;
+
+    let repayment = trans.query(&format!("INSERT INTO repayments (request_id, amount, recorded_by) \
+                                          VALUES ($1, $2, $3) RETURNING {};", models::REPAYMENT_COLUMNS),
+                                &[&id, &amount, &caller])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?
+        .iter()
+        .next()
+        .map(|row| Repayment::from_row(&row))
+        .ok_or_else(|| Error::from(ErrorKind::InternalServerError))?;
+
+    trans.execute("INSERT INTO request_events (request_id, kind, actor, amount) VALUES ($1, 'repayment', $2, $3);",
+                  &[&id, &caller, &repayment.amount])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?;
+
+    // Paying while an instalment is already overdue is late, which starts the lending policy's cooling off.
+    let repaid_before = paid.iter().map(|repayment| repayment.amount).sum();
+    if schedule::is_overdue(&trans, id, repaid_before, Utc::today().naive_utc())? {
+        trans.execute("INSERT INTO request_events (request_id, kind, actor, amount) \
+                       VALUES ($1, 'late_repayment', $2, $3);", &[&id, &caller, &repayment.amount])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+    }
+
+    paid.push(repayment.clone());
+    let request = if repaid_in_full {
+        let next = request.status.transition(Status::Repaid)?;
+        let repaid = trans.query(&format!("UPDATE requests SET status = $1, updated_at = now() WHERE id = $2 \
+                                           RETURNING {};", models::REQUEST_COLUMNS),
+                                 &[&next.as_ref(), &id])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?
+            .iter()
+            .next()
+            .ok_or_else(|| Error::from(ErrorKind::InternalServerError))
+            .and_then(|row| models::Request::from_row(&row))?;
+        trans.execute("INSERT INTO request_events (request_id, kind, actor) VALUES ($1, $2, $3);",
+                      &[&id, &next.as_ref(), &caller])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        repaid
+    } else {
+        request
+    };
+
+    trans.commit()
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?;
+
+    json::response(status::Created, &Receipt {
+        balance: request.balance(&paid),
+        repayment,
+        request
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone};
+
+    use super::*;
+    use currency::Currency;
+    use interest;
+
+    fn pence(n: i64) -> Money {
+        Money::from_minor_units(n)
+    }
+
+    fn disbursed(amount: i64, at: DateTime<Utc>) -> models::Request {
+        models::Request {
+            id: 1,
+            user_id: "borrower".to_string(),
+            amount: pence(amount),
+            currency: Currency::default(),
+            status: Status::Disbursed,
+            interest_method: interest::Method::Simple,
+            interest_rate_bps: 0,
+            disbursed_at: Some(at),
+            created_at: at,
+            updated_at: at
+        }
+    }
+
+    fn repayment(amount: i64, at: DateTime<Utc>) -> Repayment {
+        Repayment {
+            id: 1,
+            request_id: 1,
+            amount: pence(amount),
+            recorded_by: "borrower".to_string(),
+            paid_at: at
+        }
+    }
+
+    #[test]
+    fn overpayments_are_refused() {
+        match *settles(pence(10_001), pence(10_000)).unwrap_err().kind() {
+            ErrorKind::OverpaymentError(amount, owed) => {
+                assert_eq!(pence(10_001), amount);
+                assert_eq!(pence(10_000), owed);
+            },
+            ref kind => panic!("Expected an overpayment, got {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn only_paying_exactly_what_is_owed_repays() {
+        assert!(settles(pence(10_000), pence(10_000)).unwrap());
+        assert!(!settles(pence(9_999), pence(10_000)).unwrap());
+    }
+
+    #[test]
+    fn partial_repayments_reduce_what_is_owed() {
+        let at = Utc.ymd(2017, 1, 1).and_hms(12, 0, 0);
+        let request = disbursed(10_000, at);
+        let first = repayment(4_000, at + Duration::days(1));
+        assert_eq!(pence(6_000), request.balance(&[first.clone()]).total_owed);
+        assert!(settles(pence(6_000), request.balance(&[first.clone()]).total_owed).unwrap());
+        assert!(request.balance(&[first, repayment(6_000, at + Duration::days(2))]).total_owed.is_zero());
+    }
+}
@@ -0,0 +1,91 @@
+use iron::prelude::*;
+use iron::status;
+use postgres::{Connection, GenericConnection};
+use chrono::{NaiveDate, Utc};
+
+use errors::*;
+use models::{self, Instalment, NewRequest};
+use json;
+use money::Money;
+use schedule::{self, Frequency, Plan, ScheduledInstalment, InstalmentStatus};
+use super::{detail, repayment};
+
+#[derive(Debug, Serialize)]
+struct Schedule {
+    request_id: i32,
+    repaid: Money,
+    instalments: Vec<ScheduledInstalment>
+}
+
+impl NewRequest {
+    /// The repayment plan asked for, either a single `due_date` or `instalments` starting on
+    /// `first_due`, for a request of `principal`.
+    pub fn plan(&self, principal: Money) -> Result<Option<Plan>> {
+        self.requested_plan()
+            .and_then(|plan| match plan {
+                Some(plan) => plan.validate(principal, Utc::today().naive_utc()).map(|_| Some(plan)),
+                None => Ok(None)
+            })
+            .chain_err(|| ErrorKind::BadRequestError)
+    }
+
+    fn requested_plan(&self) -> Result<Option<Plan>> {
+        match (self.due_date, self.instalments) {
+            (Some(_), Some(_)) => Err(Error::from(ErrorKind::InvalidRequestDataError("due_date".to_string()))),
+            (Some(due_date), None) => Ok(Some(Plan::due_on(due_date))),
+            (None, Some(count)) => self.first_due
+                .ok_or_else(|| Error::from(ErrorKind::MissingRequestDataError("first_due".to_string())))
+                .map(|first_due| Some(Plan {
+                    count,
+                    frequency: self.frequency.unwrap_or(Frequency::Monthly),
+                    first_due
+                })),
+            (None, None) => Ok(None)
+        }
+    }
+}
+
+pub fn create<C: GenericConnection>(con: &C, request: &models::Request, plan: &Plan) -> Result<()> {
+    let insert = con.prepare("INSERT INTO instalments (request_id, number, due_date, amount) VALUES ($1, $2, $3, $4);")
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?;
+    for (number, due_date, amount) in plan.instalments(request.amount) {
+        insert.execute(&[&request.id, &number, &due_date, &amount])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+    }
+    Ok(())
+}
+
+pub fn instalments<C: GenericConnection>(con: &C, id: i32) -> Result<Vec<Instalment>> {
+    con.query(&format!("SELECT {} FROM instalments WHERE request_id = $1 ORDER BY number;",
+                       models::INSTALMENT_COLUMNS), &[&id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+        .map(|rows| rows.iter()
+            .map(|row| Instalment::from_row(&row))
+            .collect())
+}
+
+/// Whether any instalment of request `id` is overdue once `repaid` has been allocated.
+pub fn is_overdue<C: GenericConnection>(con: &C, id: i32, repaid: Money, today: NaiveDate) -> Result<bool> {
+    instalments(con, id).map(|instalments| schedule::allocate(&instalments, repaid, today).iter()
+        .any(|scheduled| scheduled.status == InstalmentStatus::Overdue))
+}
+
+pub fn schedule(con: &Connection, caller: &str, view_all: bool, id: i32) -> Result<Response> {
+    let request = detail::find(con, id)?;
+    if !view_all && request.user_id != caller {
+        bail!(ErrorKind::ForbiddenError)
+    }
+
+    let repaid: Money = repayment::repayments(con, id)?.iter()
+        .map(|repayment| repayment.amount)
+        .sum();
+    let instalments = instalments(con, id)?;
+    json::response(status::Ok, &Schedule {
+        request_id: id,
+        repaid,
+        instalments: schedule::allocate(&instalments, repaid, Utc::today().naive_utc())
+    })
+}
@@ -0,0 +1,46 @@
+use iron::prelude::*;
+use iron::status;
+use postgres::Connection;
+
+use errors::*;
+use models;
+use json;
+use lifecycle::{Action, Status};
+use super::detail;
+
+pub fn transition(con: &Connection, caller: &str, is_admin: bool, id: i32, action: Action) -> Result<Response> {
+    let request = detail::find(con, id)?;
+    if action.admin_only() && !is_admin {
+        bail!(ErrorKind::ForbiddenError)
+    }
+    if !action.admin_only() && request.user_id != caller {
+        bail!(ErrorKind::ForbiddenError)
+    }
+
+    let next = request.status.transition(action.target())?;
+
+    let trans = con.transaction()
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?;
+
+    let disbursed = next == Status::Disbursed;
+    // Guard on the current status so a concurrent transition can't be overwritten.
+    let updated = trans.query(&format!("UPDATE requests SET status = $1, updated_at = now(), \
+                                        disbursed_at = CASE WHEN $4 THEN now() ELSE disbursed_at END \
+                                        WHERE id = $2 AND status = $3 RETURNING {};", models::REQUEST_COLUMNS),
+                              &[&next.as_ref(), &id, &request.status.as_ref(), &disbursed])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?
+        .iter()
+        .next()
+        .ok_or_else(|| Error::from(ErrorKind::InvalidTransitionError(request.status.to_string(), next.to_string())))
+        .and_then(|row| models::Request::from_row(&row))?;
+
+    trans.execute("INSERT INTO request_events (request_id, kind, actor) VALUES ($1, $2, $3);",
+                  &[&id, &next.as_ref(), &caller])
+        .and_then(|_| trans.commit())
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)?;
+
+    json::response(status::Ok, &updated)
+}
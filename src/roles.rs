@@ -0,0 +1,119 @@
+//! What each user is allowed to do.
+//!
+//! Admins run the platform: they approve, reject and disburse loans, set rates and limits and
+//! manage users. Borrowers file and repay their own requests. Auditors can read everything but
+//! change nothing. A user can hold any number of roles, and new users are borrowers.
+
+use std::fmt;
+use std::str::FromStr;
+
+use postgres::GenericConnection;
+
+use errors::*;
+use models;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Borrower,
+    Auditor
+}
+
+impl AsRef<str> for Role {
+    fn as_ref(&self) -> &str {
+        match *self {
+            Role::Admin => "admin",
+            Role::Borrower => "borrower",
+            Role::Auditor => "auditor"
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Role> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "borrower" => Ok(Role::Borrower),
+            "auditor" => Ok(Role::Auditor),
+            _ => Err(Error::from(ErrorKind::InvalidRequestDataError("role".to_string())))
+        }
+    }
+}
+
+pub fn roles<C: GenericConnection>(con: &C, user_id: &str) -> Result<Vec<Role>> {
+    con.query("SELECT role FROM user_roles WHERE user_id = $1 ORDER BY role;", &[&user_id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+        .and_then(|rows| rows.iter()
+            .map(|row| models::parse_column(&row, "role"))
+            .collect())
+}
+
+/// Gives `user_id` the `role`, returning false if they already had it.
+pub fn grant<C: GenericConnection>(con: &C, user_id: &str, role: Role, granted_by: Option<&str>) -> Result<bool> {
+    con.execute("INSERT INTO user_roles (user_id, role, granted_by) VALUES ($1, $2, $3) \
+                 ON CONFLICT (user_id, role) DO NOTHING;", &[&user_id, &role.as_ref(), &granted_by])
+        .map(|inserted| inserted > 0)
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+}
+
+/// Takes the `role` away from `user_id`, returning false if they didn't have it. The last admin
+/// can't be removed, as nobody would be left to grant the role again.
+pub fn revoke<C: GenericConnection>(con: &C, user_id: &str, role: Role) -> Result<bool> {
+    if role == Role::Admin {
+        // Lock the admins so two admins can't remove each other at once.
+        let admins = con.query("SELECT user_id FROM user_roles WHERE role = 'admin' FOR UPDATE;", &[])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        if admins.len() == 1 && admins.get(0).get::<_, String>("user_id") == user_id {
+            bail!(ErrorKind::LastAdminError)
+        }
+    }
+
+    con.execute("DELETE FROM user_roles WHERE user_id = $1 AND role = $2;", &[&user_id, &role.as_ref()])
+        .map(|deleted| deleted > 0)
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+}
+
+/// The roles `user_id` signs in with. The `admin` from the config, if there is one, is made an
+/// admin if there isn't one yet, and anyone without a role becomes a borrower.
+pub fn sign_in<C: GenericConnection>(con: &C, admin: Option<&str>, user_id: &str) -> Result<Vec<Role>> {
+    if admin == Some(user_id) {
+        con.execute("INSERT INTO user_roles (user_id, role) SELECT $1, 'admin' \
+                     WHERE NOT EXISTS (SELECT 1 FROM user_roles WHERE role = 'admin') \
+                     ON CONFLICT (user_id, role) DO NOTHING;", &[&user_id])
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+    }
+
+    let roles = roles(con, user_id)?;
+    if !roles.is_empty() {
+        return Ok(roles)
+    }
+    grant(con, user_id, Role::Borrower, None)?;
+    Ok(vec![Role::Borrower])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_round_trip_through_strings() {
+        for role in &[Role::Admin, Role::Borrower, Role::Auditor] {
+            assert_eq!(*role, role.as_ref().parse::<Role>().unwrap());
+        }
+        assert!("lender".parse::<Role>().is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Repayment schedules.
+//!
+//! A schedule splits the principal of a request into instalments due on set dates. A single due
+//! date is a schedule with one instalment. Repayments are allocated to instalments oldest first,
+//! so an instalment is only paid once every earlier one has been.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDate};
+
+use errors::*;
+use money::Money;
+use models::Instalment;
+
+/// The most instalments a request can be split into.
+pub const MAX_INSTALMENTS: i32 = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Frequency {
+    Weekly,
+    Monthly
+}
+
+impl AsRef<str> for Frequency {
+    fn as_ref(&self) -> &str {
+        match *self {
+            Frequency::Weekly => "weekly",
+            Frequency::Monthly => "monthly"
+        }
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl FromStr for Frequency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Frequency> {
+        match s {
+            "weekly" => Ok(Frequency::Weekly),
+            "monthly" => Ok(Frequency::Monthly),
+            _ => Err(Error::from(ErrorKind::InvalidRequestDataError("frequency".to_string())))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstalmentStatus {
+    Upcoming,
+    Paid,
+    PartiallyPaid,
+    Overdue
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plan {
+    pub count: i32,
+    pub frequency: Frequency,
+    pub first_due: NaiveDate
+}
+
+impl Plan {
+    /// A plan with one instalment of the whole amount on `due`.
+    pub fn due_on(due: NaiveDate) -> Plan {
+        Plan {
+            count: 1,
+            frequency: Frequency::Monthly,
+            first_due: due
+        }
+    }
+
+    /// Checks the plan can be used for a request of `principal` made on `today`. Every instalment
+    /// must be for at least one penny.
+    pub fn validate(&self, principal: Money, today: NaiveDate) -> Result<()> {
+        if self.count < 1 || self.count > MAX_INSTALMENTS || i64::from(self.count) > principal.minor_units() {
+            bail!(ErrorKind::InvalidRequestDataError("instalments".to_string()))
+        }
+        if self.first_due <= today {
+            bail!(ErrorKind::InvalidRequestDataError("first_due".to_string()))
+        }
+        Ok(())
+    }
+
+    /// The date the instalment `number`, counting from one, falls due.
+    pub fn due_date(&self, number: i32) -> NaiveDate {
+        let n = number - 1;
+        match self.frequency {
+            Frequency::Weekly => self.first_due + Duration::weeks(i64::from(n)),
+            Frequency::Monthly => add_months(self.first_due, n)
+        }
+    }
+
+    /// The number, due date and amount of each instalment. The principal is split evenly, with the
+    /// last instalment taking any pence left over.
+    pub fn instalments(&self, principal: Money) -> Vec<(i32, NaiveDate, Money)> {
+        let count = i64::from(self.count);
+        let each = principal.minor_units() / count;
+        let last = principal.minor_units() - each * (count - 1);
+        (1..self.count + 1)
+            .map(|number| {
+                let amount = if number == self.count { last } else { each };
+                (number, self.due_date(number), Money::from_minor_units(amount))
+            })
+            .collect()
+    }
+}
+
+/// The same day `months` months after `date`, or the last day of that month if it is shorter.
+fn add_months(date: NaiveDate, months: i32) -> NaiveDate {
+    let total = date.year() * 12 + date.month0() as i32 + months;
+    let (year, month) = (total / 12, total % 12 + 1);
+    let day = date.day().min(days_in_month(year, month as u32));
+    NaiveDate::from_ymd(year, month as u32, day)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    NaiveDate::from_ymd(next_year, next_month, 1).pred().day()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledInstalment {
+    pub number: i32,
+    pub due_date: NaiveDate,
+    pub amount: Money,
+    pub paid: Money,
+    pub status: InstalmentStatus
+}
+
+/// Allocates `repaid` to `instalments` oldest first and works out the status of each as of
+/// `today`. Instalments must be in order.
+pub fn allocate(instalments: &[Instalment], repaid: Money, today: NaiveDate) -> Vec<ScheduledInstalment> {
+    let mut remaining = repaid;
+    instalments.iter()
+        .map(|instalment| {
+            let paid = remaining.min(instalment.amount);
+            remaining = remaining - paid;
+            let status = if paid == instalment.amount {
+                InstalmentStatus::Paid
+            } else if instalment.due_date < today {
+                InstalmentStatus::Overdue
+            } else if paid.is_zero() {
+                InstalmentStatus::Upcoming
+            } else {
+                InstalmentStatus::PartiallyPaid
+            };
+            ScheduledInstalment {
+                number: instalment.number,
+                due_date: instalment.due_date,
+                amount: instalment.amount,
+                paid,
+                status
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    fn pence(n: i64) -> Money {
+        Money::from_minor_units(n)
+    }
+
+    fn schedule(plan: &Plan, principal: i64) -> Vec<Instalment> {
+        plan.instalments(pence(principal)).into_iter()
+            .map(|(number, due_date, amount)| Instalment {
+                id: number,
+                request_id: 1,
+                number,
+                due_date,
+                amount
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_principal_evenly() {
+        let plan = Plan { count: 3, frequency: Frequency::Weekly, first_due: date(2017, 12, 29) };
+        assert_eq!(vec![(1, date(2017, 12, 29), pence(3333)),
+                        (2, date(2018, 1, 5), pence(3333)),
+                        (3, date(2018, 1, 12), pence(3334))],
+                   plan.instalments(pence(10_000)));
+    }
+
+    #[test]
+    fn monthly_instalments_keep_the_day_where_they_can() {
+        let plan = Plan { count: 4, frequency: Frequency::Monthly, first_due: date(2019, 11, 30) };
+        let dates = plan.instalments(pence(400)).into_iter().map(|(_, due, _)| due).collect::<Vec<_>>();
+        assert_eq!(vec![date(2019, 11, 30), date(2019, 12, 30), date(2020, 1, 30), date(2020, 2, 29)], dates);
+    }
+
+    #[test]
+    fn rejects_bad_plans() {
+        let today = date(2017, 6, 1);
+        let principal = pence(10_000);
+        assert!(Plan::due_on(date(2017, 6, 2)).validate(principal, today).is_ok());
+        assert!(Plan::due_on(today).validate(principal, today).is_err());
+        assert!(Plan { count: 0, frequency: Frequency::Weekly, first_due: date(2017, 7, 1) }
+            .validate(principal, today).is_err());
+        assert!(Plan { count: MAX_INSTALMENTS + 1, frequency: Frequency::Weekly, first_due: date(2017, 7, 1) }
+            .validate(principal, today).is_err());
+    }
+
+    #[test]
+    fn every_instalment_is_for_something() {
+        let today = date(2017, 6, 1);
+        let plan = Plan { count: 3, frequency: Frequency::Weekly, first_due: date(2017, 7, 1) };
+        assert!(plan.validate(pence(3), today).is_ok());
+        assert!(plan.validate(pence(2), today).is_err());
+    }
+
+    #[test]
+    fn allocates_oldest_first() {
+        let plan = Plan { count: 3, frequency: Frequency::Monthly, first_due: date(2017, 1, 1) };
+        let instalments = schedule(&plan, 300);
+        let statuses = |repaid, today| allocate(&instalments, pence(repaid), today).into_iter()
+            .map(|scheduled| (scheduled.paid.minor_units(), scheduled.status))
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![(0, InstalmentStatus::Upcoming), (0, InstalmentStatus::Upcoming),
+                        (0, InstalmentStatus::Upcoming)],
+                   statuses(0, date(2016, 12, 1)));
+        assert_eq!(vec![(100, InstalmentStatus::Paid), (50, InstalmentStatus::PartiallyPaid),
+                        (0, InstalmentStatus::Upcoming)],
+                   statuses(150, date(2017, 1, 15)));
+        assert_eq!(vec![(100, InstalmentStatus::Paid), (50, InstalmentStatus::Overdue),
+                        (0, InstalmentStatus::Upcoming)],
+                   statuses(150, date(2017, 2, 2)));
+        assert_eq!(vec![(100, InstalmentStatus::Paid), (100, InstalmentStatus::Paid), (100, InstalmentStatus::Paid)],
+                   statuses(300, date(2018, 1, 1)));
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use iron::prelude::*;
+use iron::Handler;
+use iron::method::Method;
+use iron::status;
+use chrono::{DateTime, Utc};
+
+use errors::*;
+use providers::{AuthenticatedUser, Database, Sessions};
+use models::Token;
+use json;
+use params::{self, caller};
+
+/// Lists and revokes the caller's sessions.
+pub struct SessionsHandler {
+    sessions: Arc<Sessions>
+}
+
+/// Ends the session the request was made with.
+pub struct LogoutHandler {
+    sessions: Arc<Sessions>
+}
+
+#[derive(Debug, Serialize)]
+struct ActiveSession {
+    id: i32,
+    created_at: DateTime<Utc>,
+    last_used_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    ip: String,
+    user_agent: Option<String>,
+    /// Whether this is the session the list was asked for with.
+    current: bool
+}
+
+impl ActiveSession {
+    fn new(token: Token, current: Option<i32>) -> ActiveSession {
+        ActiveSession {
+            current: current == Some(token.id),
+            id: token.id,
+            created_at: token.created_at,
+            last_used_at: token.last_used_at,
+            expires_at: token.expires_at,
+            ip: token.ip,
+            user_agent: token.user_agent
+        }
+    }
+}
+
+/// The session of `caller`, who must have used a session token rather than a personal token.
+fn session(caller: &AuthenticatedUser) -> Result<i32> {
+    caller.session.ok_or_else(|| Error::from(ErrorKind::PersonalTokenNotAllowedError))
+}
+
+impl SessionsHandler {
+    pub fn new(sessions: Arc<Sessions>) -> SessionsHandler {
+        SessionsHandler {
+            sessions
+        }
+    }
+
+    fn list(&self, req: &mut Request) -> Result<Response> {
+        let caller = caller(req)?;
+        let current = session(&caller)?;
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let sessions = self.sessions.list(&*con, &caller.subject)?
+            .into_iter()
+            .map(|token| ActiveSession::new(token, Some(current)))
+            .collect::<Vec<ActiveSession>>();
+        json::response(status::Ok, &sessions)
+    }
+
+    fn revoke(&self, req: &mut Request, id: i32) -> Result<Response> {
+        let caller = caller(req)?;
+        session(&caller)?;
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        if !self.sessions.revoke(&*con, &caller.subject, id)? {
+            bail!(ErrorKind::NotFoundError(format!("session {}", id)))
+        }
+        Ok(Response::with(status::NoContent))
+    }
+}
+
+impl Handler for SessionsHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = params::path(req);
+
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Get, 0) => self.list(req),
+            (Method::Delete, 1) => params::id("session", &path[0]).and_then(|id| self.revoke(req, id)),
+            (_, 0) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support GET!"))),
+            (_, 1) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support DELETE!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+}
+
+impl LogoutHandler {
+    pub fn new(sessions: Arc<Sessions>) -> LogoutHandler {
+        LogoutHandler {
+            sessions
+        }
+    }
+
+    fn logout(&self, req: &mut Request) -> Result<Response> {
+        let caller = caller(req)?;
+        let current = session(&caller)?;
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        // Already revoked is as good as logged out.
+        self.sessions.revoke(&*con, &caller.subject, current)?;
+        Ok(Response::with(status::NoContent))
+    }
+}
+
+impl Handler for LogoutHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = params::path(req);
+
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Post, 0) => self.logout(req),
+            (_, 0) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support POST!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+}
@@ -0,0 +1,117 @@
+use iron::prelude::*;
+use iron::Handler;
+use iron::method::Method;
+use iron::status;
+use urlencoded::QueryResult;
+use chrono::Utc;
+
+use errors::*;
+use providers::Database;
+use models::{NewPersonalToken, PersonalToken};
+use json;
+use params::{self, body, caller, required_value, single_value};
+use personal_tokens;
+
+pub struct TokensHandler {
+}
+
+/// A new personal token, the only time the token itself is sent.
+#[derive(Debug, Serialize)]
+struct CreatedToken {
+    token: String,
+    details: PersonalToken
+}
+
+impl NewPersonalToken {
+    fn from_query(map_res: QueryResult) -> Result<NewPersonalToken> {
+        let mut map = map_res.map_err(|err| Error::from(ErrorKind::RequestDecodeError(err)))
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let name = required_value(&mut map, "name")
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        // Scopes can be given once each or together, separated by commas or spaces.
+        let scopes = map.remove("scopes")
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|scopes| scopes.split(|c| c == ',' || c == ' ').map(String::from).collect::<Vec<String>>())
+            .filter(|scope| !scope.is_empty())
+            .map(|scope| scope.parse())
+            .collect::<Result<Vec<_>>>()
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let expires_on = match single_value(&mut map, "expires_on").chain_err(|| ErrorKind::BadRequestError)? {
+            Some(expires_on) => Some(params::date("expires_on", &expires_on).chain_err(|| ErrorKind::BadRequestError)?),
+            None => None
+        };
+
+        Ok(NewPersonalToken {
+            name,
+            scopes,
+            expires_on
+        })
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            bail!(ErrorKind::InvalidRequestDataError("name".to_string()))
+        }
+        if self.scopes.is_empty() {
+            bail!(ErrorKind::MissingRequestDataError("scopes".to_string()))
+        }
+        if self.expires_on.map_or(false, |expires_on| expires_on <= Utc::today().naive_utc()) {
+            bail!(ErrorKind::InvalidRequestDataError("expires_on".to_string()))
+        }
+        Ok(())
+    }
+}
+
+impl TokensHandler {
+    pub fn new() -> TokensHandler {
+        TokensHandler {}
+    }
+
+    fn list(&self, req: &mut Request) -> Result<Response> {
+        let caller = caller(req)?;
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        json::response(status::Ok, &personal_tokens::list(&*con, &caller.subject)?)
+    }
+
+    fn create(&self, req: &mut Request) -> Result<Response> {
+        let caller = caller(req)?;
+        let new = body(req, NewPersonalToken::from_query)?;
+        new.validate().chain_err(|| ErrorKind::BadRequestError)?;
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let (details, token) = personal_tokens::create(&*con, &caller.subject, &new)?;
+        json::response(status::Created, &CreatedToken {
+            token,
+            details
+        })
+    }
+
+    fn revoke(&self, req: &mut Request, id: i32) -> Result<Response> {
+        let caller = caller(req)?;
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        if !personal_tokens::revoke(&*con, &caller.subject, id)? {
+            bail!(ErrorKind::NotFoundError(format!("token {}", id)))
+        }
+        Ok(Response::with(status::NoContent))
+    }
+}
+
+impl Handler for TokensHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = params::path(req);
+
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Get, 0) => self.list(req),
+            (Method::Post, 0) => self.create(req),
+            (Method::Delete, 1) => params::id("token", &path[0]).and_then(|id| self.revoke(req, id)),
+            (_, 0) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support GET and POST!"))),
+            (_, 1) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support DELETE!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+}
@@ -0,0 +1,144 @@
+use iron::prelude::*;
+use iron::Handler;
+use iron::method::Method;
+use iron::status;
+use postgres::GenericConnection;
+use urlencoded::QueryResult;
+
+use errors::*;
+use providers::Database;
+use models;
+use json;
+use params::{self, body, caller, required_value};
+use roles::{self, Role};
+
+pub struct UsersHandler {
+}
+
+#[derive(Debug, Serialize)]
+struct UserRoles {
+    id: String,
+    name: String,
+    email: String,
+    roles: Vec<Role>
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NewRole {
+    role: Role
+}
+
+impl NewRole {
+    fn from_query(map_res: QueryResult) -> Result<NewRole> {
+        let mut map = map_res.map_err(|err| Error::from(ErrorKind::RequestDecodeError(err)))
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        let role = required_value(&mut map, "role")
+            .and_then(|role| role.parse())
+            .chain_err(|| ErrorKind::BadRequestError)?;
+
+        Ok(NewRole {
+            role
+        })
+    }
+}
+
+fn users<C: GenericConnection>(con: &C, id: Option<&str>) -> Result<Vec<UserRoles>> {
+    con.query(&format!("SELECT {}, ARRAY(SELECT role FROM user_roles WHERE user_id = users.id ORDER BY role) \
+                        AS roles FROM users WHERE $1::VARCHAR IS NULL OR id = $1 ORDER BY id;",
+                       models::USER_COLUMNS), &[&id])
+        .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+        .chain_err(|| ErrorKind::InternalServerError)
+        .and_then(|rows| rows.iter()
+            .map(|row| models::parse_values("roles", row.get("roles")).map(|roles| UserRoles {
+                id: row.get("id"),
+                name: row.get("name"),
+                email: row.get("email"),
+                roles
+            }))
+            .collect())
+}
+
+fn find<C: GenericConnection>(con: &C, id: &str) -> Result<UserRoles> {
+    users(con, Some(id))?.into_iter()
+        .next()
+        .ok_or_else(|| Error::from(ErrorKind::NotFoundError(format!("user {}", id))))
+}
+
+impl UsersHandler {
+    pub fn new() -> UsersHandler {
+        UsersHandler {}
+    }
+
+    fn list(&self, req: &mut Request) -> Result<Response> {
+        if !caller(req)?.is_admin() {
+            bail!(ErrorKind::ForbiddenError)
+        }
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        json::response(status::Ok, &users(&*con, None)?)
+    }
+
+    fn detail(&self, req: &mut Request, id: &str) -> Result<Response> {
+        let caller = caller(req)?;
+        if !caller.is_admin() && caller.subject != id {
+            bail!(ErrorKind::ForbiddenError)
+        }
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        json::response(status::Ok, &find(&*con, id)?)
+    }
+
+    fn grant(&self, req: &mut Request, id: &str) -> Result<Response> {
+        let caller = caller(req)?;
+        if !caller.is_admin() {
+            bail!(ErrorKind::ForbiddenError)
+        }
+        let new = body(req, NewRole::from_query)?;
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        find(&*con, id)?;
+        roles::grant(&*con, id, new.role, Some(&caller.subject))?;
+        json::response(status::Ok, &find(&*con, id)?)
+    }
+
+    fn revoke(&self, req: &mut Request, id: &str, role: &str) -> Result<Response> {
+        if !caller(req)?.is_admin() {
+            bail!(ErrorKind::ForbiddenError)
+        }
+        let role = role.parse::<Role>()
+            .map_err(|_| Error::from(ErrorKind::NotFoundError(format!("role {}", role))))?;
+
+        let con = Database::connection(req).chain_err(|| ErrorKind::InternalServerError)?;
+        let trans = con.transaction()
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        if !roles::revoke(&trans, id, role)? {
+            bail!(ErrorKind::NotFoundError(format!("role {} of user {}", role, id)))
+        }
+        trans.commit()
+            .map_err(|err| Error::from(ErrorKind::PostgresError(err)))
+            .chain_err(|| ErrorKind::InternalServerError)?;
+        Ok(Response::with(status::NoContent))
+    }
+}
+
+impl Handler for UsersHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let path = params::path(req);
+
+        let response = match (req.method.clone(), path.len()) {
+            (Method::Get, 0) => self.list(req),
+            (Method::Get, 1) => self.detail(req, &path[0]),
+            (Method::Post, 2) if path[1] == "roles" => self.grant(req, &path[0]),
+            (Method::Delete, 3) if path[1] == "roles" => self.revoke(req, &path[0], &path[2]),
+            (_, 0) | (_, 1) => return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support GET!"))),
+            (_, 2) if path[1] == "roles" =>
+                return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support POST!"))),
+            (_, 3) if path[1] == "roles" =>
+                return Ok(Response::with((status::MethodNotAllowed, "This endpoint only support DELETE!"))),
+            _ => return Ok(Response::with(status::NotFound))
+        };
+        response.map_err(|err| IronError::new(err, status::InternalServerError))
+    }
+}